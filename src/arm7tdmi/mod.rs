@@ -93,7 +93,9 @@ impl Cpu {
         self.reg.r[LR_INDEX] = 0;
     }
 
-    pub fn skip_bios(&mut self, bus: &impl Bus) {
+    /// Sets up the registers like the BIOS does before jumping to `entry_addr`. Like BX, bit 0 of
+    /// `entry_addr` selects the THUMB state.
+    pub fn skip_bios(&mut self, bus: &impl Bus, entry_addr: u32) {
         self.reg.r[..=12].fill(0);
 
         self.reg.change_mode(OperationMode::Supervisor);
@@ -108,7 +110,12 @@ impl Cpu {
 
         self.reg.change_mode(OperationMode::System);
         self.reg.r[SP_INDEX] = 0x0300_7f00;
-        self.reg.r[PC_INDEX] = 0x0800_0000;
+        self.reg.cpsr.state = if entry_addr & 1 == 0 {
+            OperationState::Arm
+        } else {
+            OperationState::Thumb
+        };
+        self.reg.r[PC_INDEX] = entry_addr;
         self.reload_pipeline(bus);
         self.step_pipeline(bus);
     }
@@ -180,6 +187,21 @@ impl Cpu {
         self.step_pipeline(bus);
     }
 
+    /// Address of the next instruction to be executed.
+    pub fn pc(&self) -> u32 {
+        let instr_size = self.reg.cpsr.state.instr_size();
+        self.reg.r[PC_INDEX].wrapping_sub(2 * instr_size)
+    }
+
+    /// The current values of R0-R15. R15 is ahead of `pc()` due to the instruction pipeline.
+    pub fn registers(&self) -> &[u32; 16] {
+        &self.reg.r
+    }
+
+    pub fn cpsr(&self) -> u32 {
+        self.reg.cpsr.bits()
+    }
+
     pub fn raise_exception(&mut self, exception: Exception) {
         self.pending_exceptions[exception.priority()] = true;
     }
@@ -251,6 +273,20 @@ mod tests {
         assert!(!cpu.reg.cpsr.overflow);
     }
 
+    #[test]
+    fn skip_bios_works() {
+        let mut cpu = Cpu::new();
+        cpu.skip_bios(&NullBus, 0x0800_0000);
+        assert_eq!(OperationMode::System, cpu.reg.cpsr.mode);
+        assert_eq!(OperationState::Arm, cpu.reg.cpsr.state);
+        assert_eq!(0x0800_0000, cpu.pc());
+
+        // Bit 0 selects the THUMB state, like BX
+        cpu.skip_bios(&NullBus, 0x0300_0003);
+        assert_eq!(OperationState::Thumb, cpu.reg.cpsr.state);
+        assert_eq!(0x0300_0002, cpu.pc());
+    }
+
     #[test]
     fn enter_exception_works() {
         let mut cpu = Cpu::new();
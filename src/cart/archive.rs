@@ -0,0 +1,192 @@
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use flate2::read::MultiGzDecoder;
+use zip::ZipArchive;
+
+use super::MAX_ROM_LEN;
+
+const ROM_EXTENSIONS: &[&str] = &["gba", "bin", "mb"];
+
+fn has_rom_extension(name: &str) -> bool {
+    let ext = Path::new(name).extension().and_then(OsStr::to_str);
+    let ext = ext.unwrap_or_default();
+
+    ROM_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext))
+}
+
+fn too_large() -> anyhow::Error {
+    anyhow!("ROM is larger than {} MiB", MAX_ROM_LEN >> 20)
+}
+
+/// Reads a ROM image, failing if it's too large to be one, rather than trusting sizes from an
+/// archive or reading an unbounded amount of data.
+fn read_limited(reader: impl Read) -> Result<Vec<u8>> {
+    let mut rom = Vec::new();
+    reader.take(MAX_ROM_LEN as u64 + 1).read_to_end(&mut rom)?;
+    if rom.len() > MAX_ROM_LEN {
+        return Err(too_large());
+    }
+
+    Ok(rom)
+}
+
+fn extract_zip(data: Vec<u8>, entry_name: Option<&str>) -> Result<Vec<u8>> {
+    let mut zip = ZipArchive::new(Cursor::new(data)).context("failed to read zip archive")?;
+
+    let index = (0..zip.len())
+        .find(|&i| {
+            let entry = match zip.by_index(i) {
+                Ok(entry) if entry.is_file() => entry,
+                _ => return false,
+            };
+            let name = entry.name();
+
+            match entry_name {
+                Some(entry_name) => {
+                    let file_name = Path::new(name).file_name().and_then(OsStr::to_str);
+                    name == entry_name || file_name == Some(entry_name)
+                }
+                None => has_rom_extension(name),
+            }
+        })
+        .ok_or_else(|| match entry_name {
+            Some(entry_name) => anyhow!("zip archive has no file named \"{entry_name}\""),
+            None => anyhow!(
+                "zip archive has no ROM file (expected one of the extensions: {})",
+                ROM_EXTENSIONS.join(", ")
+            ),
+        })?;
+
+    let entry = zip.by_index(index)?;
+    let name = entry.name().to_owned();
+
+    read_limited(entry).with_context(|| format!("failed to extract \"{name}\" from zip archive"))
+}
+
+fn extract_gzip(data: &[u8]) -> Result<Vec<u8>> {
+    read_limited(MultiGzDecoder::new(data)).context("failed to decompress gzip file")
+}
+
+/// Returns the ROM image in `data`, which may be a raw image, or a zip or gzip archive. For zip
+/// archives, `entry_name` selects the file to extract, otherwise the first file with a ROM
+/// extension is chosen.
+pub fn extract_rom(data: Vec<u8>, entry_name: Option<&str>) -> Result<Vec<u8>> {
+    match data.get(..4) {
+        Some(b"PK\x03\x04" | b"PK\x05\x06") => extract_zip(data, entry_name),
+        Some([0x1f, 0x8b, ..]) => extract_gzip(&data),
+        _ if entry_name.is_some() => Err(anyhow!("file is not a zip archive")),
+        _ if data.len() > MAX_ROM_LEN => Err(too_large()),
+        _ => Ok(data),
+    }
+}
+
+/// Reads the ROM image at `path`; see [`extract_rom`]. Files larger than the largest ROM are
+/// rejected without reading them in full, even if they're archives.
+pub fn read_rom(path: impl AsRef<Path>, entry_name: Option<&str>) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    let data = File::open(path)
+        .map_err(anyhow::Error::from)
+        .and_then(read_limited)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    extract_rom(data, entry_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    fn make_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for &(name, data) in files {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn extract_zip_works() {
+        let zip = make_zip(&[
+            ("readme.txt", b"hello"),
+            ("roms/game.GBA", b"game"),
+            ("roms/other.gba", b"other"),
+        ]);
+        assert_eq!(b"game", &extract_rom(zip.clone(), None).unwrap()[..]);
+        assert_eq!(
+            b"other",
+            &extract_rom(zip.clone(), Some("other.gba")).unwrap()[..]
+        );
+        assert_eq!(
+            b"hello",
+            &extract_rom(zip.clone(), Some("readme.txt")).unwrap()[..]
+        );
+        assert!(extract_rom(zip, Some("missing.gba")).is_err());
+
+        let zip = make_zip(&[("readme.txt", b"hello")]);
+        assert!(extract_rom(zip, None).is_err());
+    }
+
+    #[test]
+    fn extract_gzip_works() {
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(b"game").unwrap();
+        let gz = gz.finish().unwrap();
+        assert_eq!(b"game", &extract_rom(gz, None).unwrap()[..]);
+    }
+
+    #[test]
+    fn extract_too_large_fails() {
+        let chunk = vec![0; 0x10_0000];
+        let mut gz = GzEncoder::new(Vec::new(), Compression::fast());
+        for _ in 0..=MAX_ROM_LEN / chunk.len() {
+            gz.write_all(&chunk).unwrap();
+        }
+        assert!(extract_rom(gz.finish().unwrap(), None).is_err());
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file("bomb.gba", options).unwrap();
+        for _ in 0..=MAX_ROM_LEN / chunk.len() {
+            zip.write_all(&chunk).unwrap();
+        }
+        let zip = zip.finish().unwrap().into_inner();
+        assert!(extract_rom(zip, None).is_err());
+
+        // Exactly the maximum size is fine
+        let mut gz = GzEncoder::new(Vec::new(), Compression::fast());
+        for _ in 0..MAX_ROM_LEN / chunk.len() {
+            gz.write_all(&chunk).unwrap();
+        }
+        assert_eq!(
+            MAX_ROM_LEN,
+            extract_rom(gz.finish().unwrap(), None).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn extract_raw_works() {
+        assert_eq!(b"game", &extract_rom(b"game".to_vec(), None).unwrap()[..]);
+        assert!(extract_rom(b"game".to_vec(), Some("game.gba")).is_err());
+
+        // Raw images have the same size limit as compressed ones
+        assert!(extract_rom(vec![0; MAX_ROM_LEN + 1], None).is_err());
+        assert_eq!(
+            MAX_ROM_LEN,
+            extract_rom(vec![0; MAX_ROM_LEN], None).unwrap().len()
+        );
+    }
+}
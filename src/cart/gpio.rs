@@ -0,0 +1,87 @@
+use intbits::Bits;
+
+use super::{
+    rtc::{self, Rtc},
+    sensor::{self, Gyro, Rumble, SolarSensor},
+};
+
+/// The Game Pak's 4-bit general purpose I/O port, mapped over ROM at `0x0800_00c4..=0x0800_00c9`.
+#[derive(Default, Debug)]
+pub struct Gpio {
+    pub rtc: Option<Rtc>,
+    pub solar: Option<SolarSensor>,
+    pub gyro: Option<Gyro>,
+    pub rumble: Option<Rumble>,
+
+    data: u8,
+    direction: u8,
+    readable: bool,
+}
+
+impl Gpio {
+    /// The pin states; pins configured as outputs are driven by the GBA, while the rest are driven
+    /// by the connected devices.
+    fn pins(&self) -> u8 {
+        let mut device_bits = 0;
+        if let Some(rtc) = &self.rtc {
+            device_bits |= u8::from(rtc.sio()) << rtc::PIN_SIO;
+        }
+        if let Some(solar) = &self.solar {
+            device_bits |= u8::from(solar.flag()) << sensor::SOLAR_PIN_FLAG;
+        }
+        if let Some(gyro) = &self.gyro {
+            device_bits |= u8::from(gyro.data()) << sensor::GYRO_PIN_DATA;
+        }
+
+        ((self.data & self.direction) | (device_bits & !self.direction)).bits(..4)
+    }
+
+    /// Returns `None` if `offset` (relative to the start of ROM) does not refer to a GPIO register,
+    /// or if the registers are not currently readable.
+    pub fn read(&self, offset: u32) -> Option<u8> {
+        if !self.readable {
+            return None;
+        }
+
+        match offset {
+            0xc4 => Some(self.pins()),
+            0xc6 => Some(self.direction),
+            0xc8 => Some(self.readable.into()),
+            0xc5 | 0xc7 | 0xc9 => Some(0),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, offset: u32, value: u8) {
+        match offset {
+            0xc4 => {
+                self.data = value.bits(..4);
+                let pins = self.pins();
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_pins(pins);
+                }
+                if let Some(solar) = &mut self.solar {
+                    solar.write_pins(pins);
+                }
+                if let Some(gyro) = &mut self.gyro {
+                    gyro.write_pins(pins);
+                }
+                if let Some(rumble) = &mut self.rumble {
+                    rumble.write_pins(pins);
+                }
+            }
+            0xc6 => self.direction = value.bits(..4),
+            0xc8 => self.readable = value.bit(0),
+            _ => {}
+        }
+    }
+
+    /// Returns whether a connected device has requested an interrupt.
+    pub fn step(&mut self, cycles: u32) -> bool {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.step(cycles)
+        } else {
+            false
+        }
+    }
+}
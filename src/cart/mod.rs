@@ -0,0 +1,210 @@
+pub mod archive;
+pub mod gpio;
+pub mod patch;
+pub mod rtc;
+pub mod sensor;
+
+use std::{fs, io, path::Path};
+
+use crate::arm7tdmi::{Cpu, Exception};
+
+use self::{
+    gpio::Gpio,
+    rtc::Rtc,
+    sensor::{Gyro, Rumble, SolarSensor, TiltSensor},
+};
+
+/// The size of the cartridge ROM address space, which ROM images can't be larger than.
+const MAX_ROM_LEN: usize = 0x200_0000;
+
+#[derive(Debug)]
+pub struct Cartridge {
+    rom: Vec<u8>,
+    pub sram: Box<[u8]>,
+    pub gpio: Option<Gpio>,
+    pub tilt: Option<TiltSensor>,
+}
+
+impl Cartridge {
+    pub fn new(rom: Vec<u8>) -> Self {
+        let mut cart = Self {
+            rom,
+            sram: vec![0; 0x1_0000].into_boxed_slice(),
+            gpio: None,
+            tilt: None,
+        };
+        cart.connect_known_hardware();
+
+        cart
+    }
+
+    /// Connects the extra hardware used by games that are known to need it, identified by the
+    /// first 3 characters of their game code.
+    fn connect_known_hardware(&mut self) {
+        match self.game_code().get(..3).unwrap_or_default() {
+            // Pokemon Ruby, Sapphire and Emerald, Rockman EXE 4.5, Sennen Kazoku
+            b"AXV" | b"AXP" | b"BPE" | b"BR4" | b"BKA" => {
+                self.enable_rtc();
+            }
+            // Boktai series
+            b"U3I" | b"U32" | b"U33" => {
+                self.enable_rtc();
+                self.enable_solar_sensor();
+            }
+            // WarioWare: Twisted!
+            b"RZW" => {
+                self.enable_gyro();
+                self.enable_rumble();
+            }
+            // Drill Dozer
+            b"V49" => {
+                self.enable_rumble();
+            }
+            // Yoshi Topsy-Turvy, Koro Koro Puzzle
+            b"KYG" | b"KHP" => {
+                self.enable_tilt_sensor();
+            }
+            _ => {}
+        }
+    }
+
+    /// Loads a ROM image, which may be compressed; see [`archive::read_rom`].
+    ///
+    /// If `patch_path` is `None`, a patch file next to the ROM with the same name is applied if it
+    /// exists.
+    pub fn from_file(
+        path: impl AsRef<Path>,
+        entry_name: Option<&str>,
+        patch_path: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let mut rom = archive::read_rom(&path, entry_name)?;
+        if let Some(patch_path) = patch_path
+            .map(Path::to_path_buf)
+            .or_else(|| patch::find_for_rom(&path))
+        {
+            rom = patch::apply_file(&rom, patch_path)?;
+        }
+
+        Ok(Self::new(rom))
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Reads the byte at `offset` into the cartridge's 32 MiB ROM address space. Past the end of
+    /// the ROM, nothing drives the Game Pak bus, so the low 16 bits of the halfword address that
+    /// was latched onto it are read back instead.
+    pub fn read_rom(&self, offset: u32) -> u8 {
+        let offset = offset & 0x01ff_ffff;
+        if let Some(&value) = self.rom.get(offset as usize) {
+            return value;
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        let open_bus = (offset >> 1) as u16;
+        open_bus.to_le_bytes()[offset as usize & 1]
+    }
+
+    pub fn game_code(&self) -> &[u8] {
+        self.rom.get(0xac..0xb0).unwrap_or_default()
+    }
+
+    // The enable_* functions connect the device if it isn't already.
+
+    pub fn enable_rtc(&mut self) -> &mut Rtc {
+        let gpio = self.gpio.get_or_insert_with(Gpio::default);
+        gpio.rtc.get_or_insert_with(Rtc::default)
+    }
+
+    pub fn enable_solar_sensor(&mut self) -> &mut SolarSensor {
+        let gpio = self.gpio.get_or_insert_with(Gpio::default);
+        gpio.solar.get_or_insert_with(SolarSensor::default)
+    }
+
+    pub fn enable_gyro(&mut self) -> &mut Gyro {
+        let gpio = self.gpio.get_or_insert_with(Gpio::default);
+        gpio.gyro.get_or_insert_with(Gyro::default)
+    }
+
+    pub fn enable_rumble(&mut self) -> &mut Rumble {
+        let gpio = self.gpio.get_or_insert_with(Gpio::default);
+        gpio.rumble.get_or_insert_with(Rumble::default)
+    }
+
+    pub fn enable_tilt_sensor(&mut self) -> &mut TiltSensor {
+        self.tilt.get_or_insert_with(TiltSensor::default)
+    }
+
+    pub fn step(&mut self, cpu: &mut Cpu, cycles: u32) {
+        if let Some(gpio) = &mut self.gpio {
+            if gpio.step(cycles) {
+                cpu.raise_exception(Exception::Interrupt);
+            }
+        }
+    }
+}
+
+pub struct Bios(Box<[u8]>);
+
+impl Bios {
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self(fs::read(path)?.into_boxed_slice()))
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_rom_works() {
+        let cart = Cartridge::new(vec![0x12, 0x34, 0x56]);
+        assert_eq!(0x12, cart.read_rom(0));
+        assert_eq!(0x56, cart.read_rom(2));
+        assert_eq!(0x56, cart.read_rom(2 + 0x0200_0000));
+
+        // Past the end, the halfword address is read back.
+        assert_eq!(0x00, cart.read_rom(3));
+        assert_eq!(0x02, cart.read_rom(4));
+        assert_eq!(0x00, cart.read_rom(5));
+        assert_eq!(0x03, cart.read_rom(6));
+        assert_eq!(0xcd, cart.read_rom(0x0123_579a));
+        assert_eq!(0xab, cart.read_rom(0x0123_579b));
+        assert_eq!(0xff, cart.read_rom(0x01ff_fffe));
+        assert_eq!(0xff, cart.read_rom(0x01ff_ffff));
+    }
+
+    #[test]
+    fn read_rom_non_power_of_two_works() {
+        // A 6 MiB ROM, which isn't mirrored to fill the address space.
+        #[allow(clippy::cast_possible_truncation)]
+        let rom = (0..0x60_0000_u32).map(|i| (i % 251) as u8).collect();
+        let cart = Cartridge::new(rom);
+        let last = 0x8c; // 0x5f_ffff % 251
+        assert_eq!(last, cart.read_rom(0x5f_ffff));
+        assert_eq!(0, cart.read_rom(0x0));
+
+        // Just past the end, the halfword address is read back.
+        assert_eq!(0x00, cart.read_rom(0x60_0000));
+        assert_eq!(0x00, cart.read_rom(0x60_0001));
+        assert_eq!(0x01, cart.read_rom(0x60_0002));
+        assert_eq!(0x00, cart.read_rom(0x60_0003));
+        assert_eq!(0x34, cart.read_rom(0x60_2468));
+        assert_eq!(0x12, cart.read_rom(0x60_2469));
+
+        // The 32 MiB address space is mirrored, but the ROM itself isn't.
+        assert_eq!(last, cart.read_rom(0x0200_0000 + 0x5f_ffff));
+        assert_eq!(0x00, cart.read_rom(0x0200_0000 + 0x60_0000));
+        assert_eq!(0x01, cart.read_rom(0x0200_0000 + 0x60_0002));
+        assert_eq!(
+            cart.read_rom(0x40_0000 + 0x1234),
+            cart.read_rom(0x0240_0000 + 0x1234)
+        );
+        assert_eq!(0xff, cart.read_rom(0x03ff_fffe));
+    }
+}
@@ -0,0 +1,437 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use super::MAX_ROM_LEN;
+
+const PATCH_EXTENSIONS: &[&str] = &["ips", "ups", "bps"];
+
+/// Returns the path of the patch file with the same name as `rom_path` and one of the supported
+/// patch extensions, if one exists.
+pub fn find_for_rom(rom_path: impl AsRef<Path>) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|ext| rom_path.as_ref().with_extension(ext))
+        .find(|path| path.is_file())
+}
+
+/// Applies the IPS, UPS or BPS patch in `patch` to `rom`, detecting the format from its header.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    match patch.get(..4) {
+        Some(b"PATC") if patch.starts_with(b"PATCH") => apply_ips(rom, &patch[5..]),
+        Some(b"UPS1") => apply_ups(rom, patch),
+        Some(b"BPS1") => apply_bps(rom, patch),
+        _ => Err(anyhow!("unknown patch format")),
+    }
+}
+
+pub fn apply_file(rom: &[u8], patch_path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let patch_path = patch_path.as_ref();
+    let patch = fs::read(patch_path)
+        .with_context(|| format!("failed to read patch file {}", patch_path.display()))?;
+
+    apply(rom, &patch).with_context(|| format!("failed to apply patch {}", patch_path.display()))
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.buf.get(self.pos..end))
+            .ok_or_else(|| anyhow!("unexpected end of patch"))?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn be_uint(&mut self, len: usize) -> Result<usize> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |acc, &b| (acc << 8) | usize::from(b)))
+    }
+
+    /// Reads a variable-length integer, as used by the UPS and BPS formats.
+    fn varint(&mut self) -> Result<usize> {
+        let invalid = || anyhow!("patch has an invalid number");
+        let (mut value, mut shift) = (0usize, 1usize);
+        loop {
+            let b = self.byte()?;
+            value = usize::from(b & 0x7f)
+                .checked_mul(shift)
+                .and_then(|x| x.checked_add(value))
+                .ok_or_else(invalid)?;
+            if b & 0x80 != 0 {
+                return Ok(value);
+            }
+
+            shift = shift.checked_mul(0x80).ok_or_else(invalid)?;
+            value = value.checked_add(shift).ok_or_else(invalid)?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], records: &[u8]) -> Result<Vec<u8>> {
+    let mut out = rom.to_vec();
+    let mut reader = Reader {
+        buf: records,
+        pos: 0,
+    };
+
+    loop {
+        let offset_bytes = reader.bytes(3)?;
+        if offset_bytes == b"EOF" {
+            break;
+        }
+        let offset = offset_bytes
+            .iter()
+            .fold(0, |acc, &b| (acc << 8) | usize::from(b));
+
+        let (len, fill) = match reader.be_uint(2)? {
+            // Run-length encoded record.
+            0 => (reader.be_uint(2)?, Some(reader.byte()?)),
+            len => (len, None),
+        };
+        if out.len() < offset + len {
+            out.resize(offset + len, 0);
+        }
+
+        let dst = &mut out[offset..offset + len];
+        match fill {
+            Some(fill) => dst.fill(fill),
+            None => dst.copy_from_slice(reader.bytes(len)?),
+        }
+    }
+
+    // Some IPS patches specify the size to truncate the output to after the EOF marker.
+    if let Ok(len) = reader.be_uint(3) {
+        out.truncate(len);
+    }
+
+    Ok(out)
+}
+
+/// Checks the CRC32 checksums that end UPS and BPS patches, returning the patch without them and
+/// the checksum expected for the output.
+fn verify_checksums<'a>(rom: &[u8], patch: &'a [u8]) -> Result<(&'a [u8], u32)> {
+    if patch.len() < 4 + 12 {
+        bail!("patch is too small");
+    }
+
+    let (body, footer) = patch.split_at(patch.len() - 12);
+    let crc = |i: usize| u32::from_le_bytes(footer[i..i + 4].try_into().unwrap());
+    let (rom_crc, out_crc, patch_crc) = (crc(0), crc(4), crc(8));
+
+    let actual_patch_crc = crc32fast::hash(&patch[..patch.len() - 4]);
+    if actual_patch_crc != patch_crc {
+        bail!("patch is corrupt (checksum {actual_patch_crc:08x}, expected {patch_crc:08x})");
+    }
+    let actual_rom_crc = crc32fast::hash(rom);
+    if actual_rom_crc != rom_crc {
+        bail!(
+            "patch is for a different ROM (checksum {actual_rom_crc:08x}, expected {rom_crc:08x})"
+        );
+    }
+
+    Ok((body, out_crc))
+}
+
+fn verify_output(out: &[u8], out_crc: u32) -> Result<()> {
+    let actual_out_crc = crc32fast::hash(out);
+    if actual_out_crc != out_crc {
+        bail!("patched ROM has checksum {actual_out_crc:08x}, expected {out_crc:08x}");
+    }
+
+    Ok(())
+}
+
+/// Checks the output size given by a patch, so that a corrupt one can't make us allocate an
+/// unbounded amount of memory.
+fn check_out_len(out_len: usize) -> Result<()> {
+    if out_len > MAX_ROM_LEN {
+        bail!(
+            "patched ROM size {out_len} is larger than {} MiB",
+            MAX_ROM_LEN >> 20
+        );
+    }
+
+    Ok(())
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let (body, out_crc) = verify_checksums(rom, patch)?;
+    let mut reader = Reader { buf: body, pos: 4 };
+
+    let rom_len = reader.varint()?;
+    let out_len = reader.varint()?;
+    if rom_len != rom.len() {
+        bail!(
+            "patch is for a ROM of size {rom_len}, but it has size {}",
+            rom.len()
+        );
+    }
+
+    check_out_len(out_len)?;
+
+    let mut out = rom.to_vec();
+    out.resize(out_len, 0);
+
+    let oob = || anyhow!("patch writes out of bounds");
+    let mut offset = 0usize;
+    while reader.pos < body.len() {
+        offset = offset.checked_add(reader.varint()?).ok_or_else(oob)?;
+        loop {
+            let xor = reader.byte()?;
+            if xor != 0 {
+                *out.get_mut(offset).ok_or_else(oob)? ^= xor;
+            }
+            offset = offset.checked_add(1).ok_or_else(oob)?;
+            if xor == 0 {
+                break;
+            }
+        }
+    }
+
+    verify_output(&out, out_crc)?;
+    Ok(out)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let (body, out_crc) = verify_checksums(rom, patch)?;
+    let mut reader = Reader { buf: body, pos: 4 };
+
+    let rom_len = reader.varint()?;
+    let out_len = reader.varint()?;
+    if rom_len != rom.len() {
+        bail!(
+            "patch is for a ROM of size {rom_len}, but it has size {}",
+            rom.len()
+        );
+    }
+    check_out_len(out_len)?;
+    let metadata_len = reader.varint()?;
+    reader.bytes(metadata_len)?;
+
+    let mut out = Vec::with_capacity(out_len);
+    let (mut rom_rel, mut out_rel) = (0usize, 0usize);
+    let oob = || anyhow!("patch reads out of bounds");
+    let rel_offset = |reader: &mut Reader, base: usize| -> Result<usize> {
+        let value = reader.varint()?;
+        let delta = value >> 1;
+        if value & 1 == 0 {
+            base.checked_add(delta)
+        } else {
+            base.checked_sub(delta)
+        }
+        .ok_or_else(oob)
+    };
+
+    while reader.pos < body.len() {
+        let action = reader.varint()?;
+        let len = (action >> 2) + 1;
+        if out.len() + len > out_len {
+            bail!("patch writes out of bounds");
+        }
+
+        match action & 0b11 {
+            // SourceRead
+            0 => {
+                let pos = out.len();
+                out.extend_from_slice(rom.get(pos..pos + len).ok_or_else(oob)?);
+            }
+            // TargetRead
+            1 => out.extend_from_slice(reader.bytes(len)?),
+            // SourceCopy
+            2 => {
+                let start = rel_offset(&mut reader, rom_rel)?;
+                rom_rel = start.checked_add(len).ok_or_else(oob)?;
+                out.extend_from_slice(rom.get(start..rom_rel).ok_or_else(oob)?);
+            }
+            // TargetCopy; the source and destination may overlap, so copy byte-by-byte.
+            _ => {
+                out_rel = rel_offset(&mut reader, out_rel)?;
+                for _ in 0..len {
+                    out.push(*out.get(out_rel).ok_or_else(oob)?);
+                    out_rel += 1;
+                }
+            }
+        }
+    }
+
+    if out.len() != out_len {
+        bail!("patched ROM has size {}, expected {out_len}", out.len());
+    }
+    verify_output(&out, out_crc)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_varint(mut value: usize, buf: &mut Vec<u8>) {
+        loop {
+            #[allow(clippy::cast_possible_truncation)]
+            let bits = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                buf.push(0x80 | bits);
+                break;
+            }
+
+            buf.push(bits);
+            value -= 1;
+        }
+    }
+
+    fn finish_patch(mut patch: Vec<u8>, rom: &[u8], out: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32fast::hash(rom).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(out).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(&patch).to_le_bytes());
+
+        patch
+    }
+
+    #[test]
+    fn varint_works() {
+        for value in [0, 1, 127, 128, 255, 16_511, 16_512, 0x12_3456] {
+            let mut buf = Vec::new();
+            encode_varint(value, &mut buf);
+            assert_eq!(value, Reader { buf: &buf, pos: 0 }.varint().unwrap());
+        }
+    }
+
+    #[test]
+    fn apply_ips_works() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, 0xaa, 0xbb]);
+        patch.extend_from_slice(&[0, 0, 6, 0, 0, 0, 3, 0xcc]);
+        patch.extend_from_slice(b"EOF");
+
+        let out = apply(&[0, 1, 2, 3, 4], &patch).unwrap();
+        assert_eq!([0, 0xaa, 0xbb, 3, 4, 0, 0xcc, 0xcc, 0xcc], out[..]);
+
+        patch.extend_from_slice(&[0, 0, 2]);
+        assert_eq!([0, 0xaa], apply(&[0, 1, 2, 3, 4], &patch).unwrap()[..]);
+
+        assert!(apply(&[0; 5], &patch[..patch.len() - 7]).is_err());
+    }
+
+    #[test]
+    fn apply_ups_works() {
+        let rom = [1, 2, 3, 4];
+        let expected = [1, 7, 3, 4, 5, 0];
+
+        let mut patch = b"UPS1".to_vec();
+        encode_varint(rom.len(), &mut patch);
+        encode_varint(expected.len(), &mut patch);
+        encode_varint(1, &mut patch);
+        patch.extend_from_slice(&[2 ^ 7, 0]);
+        encode_varint(1, &mut patch);
+        patch.extend_from_slice(&[5, 0]);
+        let patch = finish_patch(patch, &rom, &expected);
+
+        assert_eq!(expected, apply(&rom, &patch).unwrap()[..]);
+        assert!(apply(&[1, 2, 3, 5], &patch).is_err());
+
+        let mut corrupt = patch.clone();
+        corrupt[6] ^= 1;
+        assert!(apply(&rom, &corrupt).is_err());
+    }
+
+    #[test]
+    fn apply_ups_rejects_bad_sizes() {
+        let rom = [1, 2, 3, 4];
+
+        // Output too large to allocate
+        let mut patch = b"UPS1".to_vec();
+        encode_varint(rom.len(), &mut patch);
+        encode_varint(1 << 40, &mut patch);
+        let patch = finish_patch(patch, &rom, &rom);
+        assert!(apply(&rom, &patch).is_err());
+
+        // Offset that overflows
+        let mut patch = b"UPS1".to_vec();
+        encode_varint(rom.len(), &mut patch);
+        encode_varint(rom.len(), &mut patch);
+        encode_varint(usize::MAX, &mut patch);
+        patch.push(0);
+        let patch = finish_patch(patch, &rom, &rom);
+        assert!(apply(&rom, &patch).is_err());
+    }
+
+    #[test]
+    fn apply_bps_works() {
+        let rom = b"abcdef";
+        let expected = b"abcxyzxyzfedc";
+
+        let mut patch = b"BPS1".to_vec();
+        encode_varint(rom.len(), &mut patch);
+        encode_varint(expected.len(), &mut patch);
+        encode_varint(0, &mut patch);
+        // SourceRead "abc"
+        encode_varint((3 - 1) << 2, &mut patch);
+        // TargetRead "xyz"
+        encode_varint(((3 - 1) << 2) | 1, &mut patch);
+        patch.extend_from_slice(b"xyz");
+        // TargetCopy "xyz" from offset 3
+        encode_varint(((3 - 1) << 2) | 3, &mut patch);
+        encode_varint(3 << 1, &mut patch);
+        // SourceCopy "f", "e", "d", "c" from offsets 5 down to 2
+        for delta in [5 << 1, (2 << 1) | 1, (2 << 1) | 1, (2 << 1) | 1] {
+            encode_varint(2, &mut patch);
+            encode_varint(delta, &mut patch);
+        }
+        let patch = finish_patch(patch, rom, expected);
+
+        assert_eq!(expected, &apply(rom, &patch).unwrap()[..]);
+        assert!(apply(b"abcdeg", &patch).is_err());
+
+        // Output too large to allocate
+        let mut patch = b"BPS1".to_vec();
+        encode_varint(rom.len(), &mut patch);
+        encode_varint(MAX_ROM_LEN + 1, &mut patch);
+        encode_varint(0, &mut patch);
+        let patch = finish_patch(patch, rom, rom);
+        assert!(apply(rom, &patch).is_err());
+    }
+
+    #[test]
+    fn apply_bps_rejects_huge_lengths() {
+        let rom = b"abcdef";
+        let header = || {
+            let mut patch = b"BPS1".to_vec();
+            encode_varint(rom.len(), &mut patch);
+            encode_varint(rom.len(), &mut patch);
+            patch
+        };
+
+        // Metadata longer than the address space
+        let mut patch = header();
+        encode_varint(usize::MAX, &mut patch);
+        let patch = finish_patch(patch, rom, rom);
+        let err = apply(rom, &patch).unwrap_err();
+        assert_eq!("unexpected end of patch", err.to_string());
+
+        // SourceCopy from an offset that overflows when its length is added
+        let mut patch = header();
+        encode_varint(0, &mut patch);
+        encode_varint(((2 - 1) << 2) | 2, &mut patch);
+        encode_varint((usize::MAX >> 1) << 1, &mut patch);
+        let patch = finish_patch(patch, rom, rom);
+        assert!(apply(rom, &patch).is_err());
+    }
+}
@@ -0,0 +1,645 @@
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use intbits::Bits;
+
+const CYCLES_PER_SECOND: u64 = 1 << 24;
+
+// The S-3511 only stores a 2-digit year, which we treat as 20xx.
+const EPOCH_2000_SECS: i64 = 946_684_800;
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+// Algorithms from http://howardhinnant.github.io/date_algorithms.html.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+impl DateTime {
+    /// Converts seconds since the Unix epoch to a date and time. The weekday is 0 for Sunday.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn from_unix_secs(secs: i64) -> Self {
+        let days = secs.div_euclid(86_400);
+        let day_secs = secs.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            year: year.clamp(0, 9999) as u16,
+            month,
+            day,
+            weekday: (days + 4).rem_euclid(7) as u8,
+            hour: (day_secs / 3600) as u8,
+            minute: (day_secs / 60 % 60) as u8,
+            second: (day_secs % 60) as u8,
+        }
+    }
+
+    pub fn to_unix_secs(self) -> i64 {
+        let days = days_from_civil(self.year.into(), self.month, self.day);
+
+        days * 86_400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second)
+    }
+}
+
+impl FromStr for DateTime {
+    type Err = anyhow::Error;
+
+    /// Parses a date and time in the form "YYYY-MM-DDTHH:MM:SS" (a space may be used instead of
+    /// the "T").
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (date, time) = s
+            .split_once(['T', ' '])
+            .ok_or_else(|| anyhow!("expected a date and time like 2000-01-01T00:00:00"))?;
+
+        let parse_fields = |s: &str, sep| -> anyhow::Result<Vec<u16>> {
+            let fields = s
+                .split(sep)
+                .map(str::parse)
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("invalid field in \"{s}\""))?;
+
+            if fields.len() == 3 {
+                Ok(fields)
+            } else {
+                Err(anyhow!("expected 3 fields in \"{s}\""))
+            }
+        };
+        let date = parse_fields(date, '-')?;
+        let time = parse_fields(time, ':')?;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let (month, day, hour, minute, second) = (
+            date[1] as u8,
+            date[2] as u8,
+            time[0] as u8,
+            time[1] as u8,
+            time[2] as u8,
+        );
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(anyhow!("invalid date \"{s}\""));
+        }
+        if hour > 23 || minute > 59 || second > 59 {
+            return Err(anyhow!("invalid time \"{s}\""));
+        }
+
+        // Round-trip to normalize the date and compute the weekday.
+        Ok(Self::from_unix_secs(
+            Self {
+                year: date[0],
+                month,
+                day,
+                weekday: 0,
+                hour,
+                minute,
+                second,
+            }
+            .to_unix_secs(),
+        ))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum TimeSource {
+    /// The host's system clock, in UTC.
+    #[default]
+    Host,
+    /// Starts at the given Unix time and only advances with emulated time, so that runs are
+    /// reproducible.
+    Fixed(i64),
+}
+
+impl TimeSource {
+    #[allow(clippy::cast_possible_wrap)]
+    fn now_secs(self, cycles: u64) -> i64 {
+        match self {
+            Self::Host => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64),
+            Self::Fixed(start) => start + (cycles / CYCLES_PER_SECOND) as i64,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Command {
+    Reset,
+    Status,
+    DateTime,
+    Time,
+    Alarm,
+    ForceIrq,
+}
+
+impl Command {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(Self::Reset),
+            1 => Some(Self::Status),
+            2 => Some(Self::DateTime),
+            3 => Some(Self::Time),
+            4 => Some(Self::Alarm),
+            6 => Some(Self::ForceIrq),
+            _ => None,
+        }
+    }
+
+    fn param_len(self) -> usize {
+        match self {
+            Self::Reset | Self::ForceIrq => 0,
+            Self::Status => 1,
+            Self::DateTime => 7,
+            Self::Time => 3,
+            Self::Alarm => 2,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Transfer {
+    Idle,
+    Command,
+    Write(Command),
+    Read,
+}
+
+// STATUS register bits.
+const STATUS_MINUTE_IRQ: usize = 3;
+const STATUS_ALARM_IRQ: usize = 5;
+const STATUS_24_HOUR: usize = 6;
+const STATUS_POWER_FAIL: usize = 7;
+const STATUS_WRITE_MASK: u8 = 0b0110_1010;
+
+// GPIO pins used by the RTC.
+pub(super) const PIN_SCK: usize = 0;
+pub(super) const PIN_SIO: usize = 1;
+pub(super) const PIN_CS: usize = 2;
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+fn from_bcd(bcd: u8) -> u8 {
+    10 * bcd.bits(4..) + bcd.bits(..4)
+}
+
+/// Seiko S-3511 real-time clock, as accessed through the Game Pak GPIO port.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug)]
+pub struct Rtc {
+    time_source: TimeSource,
+    offset_secs: i64,
+    cycles: u64,
+    cycle_accum: u64,
+    last_minute: i64,
+    irq: bool,
+
+    status: u8,
+    alarm: [u8; 2],
+
+    sck: bool,
+    cs: bool,
+    sio_out: bool,
+    transfer: Transfer,
+    shift: u8,
+    bit_idx: u8,
+    buf: [u8; 7],
+    buf_len: usize,
+    buf_idx: usize,
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new(TimeSource::default())
+    }
+}
+
+impl Rtc {
+    pub fn new(time_source: TimeSource) -> Self {
+        let mut rtc = Self {
+            time_source,
+            offset_secs: 0,
+            cycles: 0,
+            cycle_accum: 0,
+            last_minute: 0,
+            irq: false,
+            status: 0,
+            alarm: [0; 2],
+            sck: false,
+            cs: false,
+            sio_out: false,
+            transfer: Transfer::Idle,
+            shift: 0,
+            bit_idx: 0,
+            buf: [0; 7],
+            buf_len: 0,
+            buf_idx: 0,
+        };
+        rtc.status.set_bit(STATUS_24_HOUR, true);
+        rtc.last_minute = rtc.now_secs().div_euclid(60);
+
+        rtc
+    }
+
+    fn now_secs(&self) -> i64 {
+        self.time_source.now_secs(self.cycles) + self.offset_secs
+    }
+
+    pub fn now(&self) -> DateTime {
+        DateTime::from_unix_secs(self.now_secs())
+    }
+
+    pub fn set_now(&mut self, date_time: DateTime) {
+        self.offset_secs = date_time.to_unix_secs() - self.time_source.now_secs(self.cycles);
+        self.last_minute = self.now_secs().div_euclid(60);
+    }
+
+    pub fn set_time_source(&mut self, time_source: TimeSource) {
+        self.time_source = time_source;
+        self.offset_secs = 0;
+        self.last_minute = self.now_secs().div_euclid(60);
+    }
+
+    /// Advances emulated time. Returns whether the RTC has requested an interrupt.
+    pub fn step(&mut self, cycles: u32) -> bool {
+        self.cycles += u64::from(cycles);
+        self.cycle_accum += u64::from(cycles);
+
+        // Checking the time is comparatively expensive, so only do it once per emulated second.
+        if self.cycle_accum >= CYCLES_PER_SECOND {
+            self.cycle_accum -= CYCLES_PER_SECOND;
+
+            let minute = self.now_secs().div_euclid(60);
+            if minute != self.last_minute {
+                self.last_minute = minute;
+                self.irq |= self.status.bit(STATUS_MINUTE_IRQ);
+
+                let now = self.now();
+                let alarm_hour = from_bcd(self.alarm[0].bits(..6));
+                let alarm_minute = from_bcd(self.alarm[1].bits(..7));
+                self.irq |= self.status.bit(STATUS_ALARM_IRQ)
+                    && self.to_24_hour(alarm_hour, self.alarm[0].bit(6)) == now.hour
+                    && alarm_minute == now.minute;
+            }
+            // TODO: bit 1 of STATUS selects a steady frequency output on the interrupt pin, which
+            //       we don't emulate.
+        }
+
+        std::mem::replace(&mut self.irq, false)
+    }
+
+    pub(super) fn sio(&self) -> bool {
+        self.sio_out
+    }
+
+    pub(super) fn write_pins(&mut self, pins: u8) {
+        let (sck, sio, cs) = (pins.bit(PIN_SCK), pins.bit(PIN_SIO), pins.bit(PIN_CS));
+        let rising_sck = !self.sck && sck;
+        let rising_cs = !self.cs && cs;
+        self.sck = sck;
+        self.cs = cs;
+
+        if !cs {
+            self.transfer = Transfer::Idle;
+            return;
+        }
+        if rising_cs {
+            self.begin_transfer(Transfer::Command);
+            return;
+        }
+        if !rising_sck {
+            return;
+        }
+
+        match self.transfer {
+            Transfer::Idle => {}
+            Transfer::Command => {
+                // The command byte is transferred MSB first.
+                self.shift = (self.shift << 1) | u8::from(sio);
+                self.bit_idx += 1;
+                if self.bit_idx == 8 {
+                    self.process_command(self.shift);
+                }
+            }
+            Transfer::Write(cmd) => {
+                // Parameter bytes are transferred LSB first.
+                self.shift.set_bit(usize::from(self.bit_idx), sio);
+                self.bit_idx += 1;
+                if self.bit_idx == 8 {
+                    self.buf[self.buf_idx] = self.shift;
+                    self.buf_idx += 1;
+                    self.shift = 0;
+                    self.bit_idx = 0;
+
+                    if self.buf_idx == self.buf_len {
+                        self.write_register(cmd);
+                        self.transfer = Transfer::Idle;
+                    }
+                }
+            }
+            Transfer::Read => {
+                self.sio_out = self.buf[self.buf_idx].bit(usize::from(self.bit_idx));
+                self.bit_idx += 1;
+                if self.bit_idx == 8 {
+                    self.bit_idx = 0;
+                    self.buf_idx += 1;
+
+                    if self.buf_idx == self.buf_len {
+                        self.transfer = Transfer::Idle;
+                    }
+                }
+            }
+        }
+    }
+
+    fn begin_transfer(&mut self, transfer: Transfer) {
+        self.transfer = transfer;
+        self.shift = 0;
+        self.bit_idx = 0;
+        self.buf_idx = 0;
+    }
+
+    fn process_command(&mut self, bits: u8) {
+        // Commands are of the form 0b0110_CCC_R, where R is set for reads.
+        let cmd = match Command::from_bits(bits.bits(1..4)) {
+            Some(cmd) if bits.bits(4..) == 0b0110 => cmd,
+            _ => {
+                self.transfer = Transfer::Idle;
+                return;
+            }
+        };
+
+        self.buf_len = cmd.param_len();
+        match cmd {
+            Command::Reset => {
+                self.status = 0;
+                self.alarm = [0; 2];
+                self.offset_secs = EPOCH_2000_SECS - self.time_source.now_secs(self.cycles);
+                self.last_minute = self.now_secs().div_euclid(60);
+            }
+            Command::ForceIrq => self.irq = true,
+            _ => {}
+        }
+
+        if self.buf_len == 0 {
+            self.transfer = Transfer::Idle;
+        } else if bits.bit(0) {
+            self.read_register(cmd);
+            self.begin_transfer(Transfer::Read);
+        } else {
+            self.begin_transfer(Transfer::Write(cmd));
+        }
+    }
+
+    fn bcd_hour(&self, hour: u8) -> u8 {
+        let mut bits = if self.status.bit(STATUS_24_HOUR) {
+            to_bcd(hour)
+        } else {
+            to_bcd(hour % 12)
+        };
+        bits.set_bit(6, hour >= 12);
+
+        bits
+    }
+
+    fn to_24_hour(&self, hour: u8, pm: bool) -> u8 {
+        if !self.status.bit(STATUS_24_HOUR) && pm && hour < 12 {
+            hour + 12
+        } else {
+            hour
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn read_register(&mut self, cmd: Command) {
+        let now = self.now();
+        let time = [
+            self.bcd_hour(now.hour),
+            to_bcd(now.minute),
+            to_bcd(now.second),
+        ];
+
+        match cmd {
+            Command::Status => self.buf[0] = self.status,
+            Command::DateTime => {
+                self.buf[..4].copy_from_slice(&[
+                    to_bcd((now.year % 100) as u8),
+                    to_bcd(now.month),
+                    to_bcd(now.day),
+                    now.weekday,
+                ]);
+                self.buf[4..7].copy_from_slice(&time);
+            }
+            Command::Time => self.buf[..3].copy_from_slice(&time),
+            Command::Alarm => self.buf[..2].copy_from_slice(&self.alarm),
+            Command::Reset | Command::ForceIrq => unreachable!(),
+        }
+    }
+
+    fn write_register(&mut self, cmd: Command) {
+        let mut now = self.now();
+        let mut set_time = |rtc: &Self, time: &[u8]| {
+            now.hour = rtc.to_24_hour(from_bcd(time[0].bits(..6)), time[0].bit(6)) % 24;
+            now.minute = from_bcd(time[1].bits(..7)).min(59);
+            now.second = from_bcd(time[2].bits(..7)).min(59);
+        };
+
+        match cmd {
+            Command::Status => {
+                self.status =
+                    (self.status & !STATUS_WRITE_MASK) | (self.buf[0] & STATUS_WRITE_MASK);
+                self.status.set_bit(STATUS_POWER_FAIL, false);
+            }
+            Command::DateTime => {
+                now.year = 2000 + u16::from(from_bcd(self.buf[0]));
+                now.month = from_bcd(self.buf[1].bits(..5)).clamp(1, 12);
+                now.day = from_bcd(self.buf[2].bits(..6)).clamp(1, 31);
+                set_time(self, &self.buf[4..7]);
+                self.set_now(now);
+            }
+            Command::Time => {
+                set_time(self, &self.buf[..3]);
+                self.set_now(now);
+            }
+            Command::Alarm => self.alarm.copy_from_slice(&self.buf[..2]),
+            Command::Reset | Command::ForceIrq => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pins(sck: bool, sio: bool, cs: bool) -> u8 {
+        let mut bits = 0;
+        bits.set_bit(PIN_SCK, sck);
+        bits.set_bit(PIN_SIO, sio);
+        bits.set_bit(PIN_CS, cs);
+
+        bits
+    }
+
+    fn begin(rtc: &mut Rtc) {
+        rtc.write_pins(pins(true, false, false));
+        rtc.write_pins(pins(true, false, true));
+    }
+
+    fn send_command(rtc: &mut Rtc, cmd: u8) {
+        begin(rtc);
+        for i in (0..8).rev() {
+            rtc.write_pins(pins(false, cmd.bit(i), true));
+            rtc.write_pins(pins(true, cmd.bit(i), true));
+        }
+    }
+
+    fn send_byte(rtc: &mut Rtc, value: u8) {
+        for i in 0..8 {
+            rtc.write_pins(pins(false, value.bit(i), true));
+            rtc.write_pins(pins(true, value.bit(i), true));
+        }
+    }
+
+    fn recv_byte(rtc: &mut Rtc) -> u8 {
+        let mut value = 0;
+        for i in 0..8 {
+            rtc.write_pins(pins(false, false, true));
+            rtc.write_pins(pins(true, false, true));
+            value.set_bit(i, rtc.sio());
+        }
+
+        value
+    }
+
+    #[test]
+    fn date_time_conversion_works() {
+        let dt = DateTime::from_unix_secs(0);
+        assert_eq!((1970, 1, 1, 4), (dt.year, dt.month, dt.day, dt.weekday));
+
+        let dt = "2004-02-29T13:37:42".parse::<DateTime>().unwrap();
+        assert_eq!((2004, 2, 29, 0), (dt.year, dt.month, dt.day, dt.weekday));
+        assert_eq!((13, 37, 42), (dt.hour, dt.minute, dt.second));
+        assert_eq!(dt, DateTime::from_unix_secs(dt.to_unix_secs()));
+
+        assert_eq!(
+            EPOCH_2000_SECS,
+            "2000-01-01 00:00:00"
+                .parse::<DateTime>()
+                .unwrap()
+                .to_unix_secs()
+        );
+        assert!("2000-13-01T00:00:00".parse::<DateTime>().is_err());
+        assert!("2000-01-01".parse::<DateTime>().is_err());
+    }
+
+    #[test]
+    fn read_date_time_works() {
+        let start = "2022-05-21T23:59:58".parse::<DateTime>().unwrap();
+        let mut rtc = Rtc::new(TimeSource::Fixed(start.to_unix_secs()));
+
+        send_command(&mut rtc, 0x65);
+        let bytes: Vec<_> = (0..7).map(|_| recv_byte(&mut rtc)).collect();
+        assert_eq!([0x22, 0x05, 0x21, 0x06, 0x40 | 0x23, 0x59, 0x58], bytes[..]);
+
+        rtc.step(2 * (1 << 24));
+        send_command(&mut rtc, 0x67);
+        let bytes: Vec<_> = (0..3).map(|_| recv_byte(&mut rtc)).collect();
+        assert_eq!([0x00, 0x00, 0x00], bytes[..]);
+        assert_eq!(22, rtc.now().day);
+    }
+
+    #[test]
+    fn write_registers_works() {
+        let mut rtc = Rtc::new(TimeSource::Fixed(0));
+
+        // Switch to 12-hour mode, then set the time to 7:30:15 PM.
+        send_command(&mut rtc, 0x62);
+        send_byte(&mut rtc, 0);
+        send_command(&mut rtc, 0x66);
+        for value in [0x40 | 0x07, 0x30, 0x15] {
+            send_byte(&mut rtc, value);
+        }
+        let now = rtc.now();
+        assert_eq!((19, 30, 15), (now.hour, now.minute, now.second));
+
+        send_command(&mut rtc, 0x64);
+        for value in [0x99, 0x12, 0x31, 0x00, 0x40 | 0x11, 0x59, 0x59] {
+            send_byte(&mut rtc, value);
+        }
+        let now = rtc.now();
+        assert_eq!((2099, 12, 31), (now.year, now.month, now.day));
+        assert_eq!((23, 59, 59), (now.hour, now.minute, now.second));
+
+        send_command(&mut rtc, 0x63);
+        assert_eq!(0, recv_byte(&mut rtc));
+
+        // Deselecting the chip mid-transfer should abort it.
+        send_command(&mut rtc, 0x62);
+        rtc.write_pins(pins(true, false, false));
+        send_command(&mut rtc, 0x63);
+        assert_eq!(0, recv_byte(&mut rtc));
+
+        send_command(&mut rtc, 0x60);
+        assert_eq!(DateTime::from_unix_secs(EPOCH_2000_SECS), rtc.now());
+    }
+
+    #[test]
+    fn irqs_work() {
+        let mut rtc = Rtc::new(TimeSource::Fixed(59));
+        assert!(!rtc.step(1));
+        send_command(&mut rtc, 0x6c);
+        assert!(rtc.step(1));
+        assert!(!rtc.step(1));
+
+        // Per-minute interrupt.
+        send_command(&mut rtc, 0x62);
+        send_byte(&mut rtc, 0x40 | 0x08);
+        assert!(rtc.step(1 << 24));
+        assert!(!rtc.step(1 << 24));
+
+        // Alarm interrupt at 00:03.
+        send_command(&mut rtc, 0x62);
+        send_byte(&mut rtc, 0x40 | 0x20);
+        send_command(&mut rtc, 0x68);
+        send_byte(&mut rtc, 0x00);
+        send_byte(&mut rtc, 0x03);
+        let irqs = (0..120).filter(|_| rtc.step(1 << 24)).count();
+        assert_eq!(1, irqs);
+    }
+}
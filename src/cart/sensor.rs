@@ -0,0 +1,217 @@
+use intbits::Bits;
+
+// GPIO pins used by the solar sensor.
+const SOLAR_PIN_CLOCK: usize = 0;
+const SOLAR_PIN_RESET: usize = 1;
+const SOLAR_PIN_CS: usize = 2;
+pub(super) const SOLAR_PIN_FLAG: usize = 3;
+
+/// The Boktai solar sensor. Games measure the light level by counting clock pulses until the
+/// sensor raises its flag, so brighter light means fewer pulses.
+#[derive(Default, Debug)]
+pub struct SolarSensor {
+    /// 0 for complete darkness, 255 for direct sunlight.
+    pub light_level: u8,
+    counter: u8,
+    threshold: u8,
+    clock: bool,
+}
+
+impl SolarSensor {
+    pub(super) fn flag(&self) -> bool {
+        self.counter >= self.threshold
+    }
+
+    pub(super) fn write_pins(&mut self, pins: u8) {
+        // Chip select is active low, which lets the sensor share pins with the RTC.
+        if pins.bit(SOLAR_PIN_CS) {
+            return;
+        }
+
+        let clock = pins.bit(SOLAR_PIN_CLOCK);
+        if pins.bit(SOLAR_PIN_RESET) {
+            // Roughly the range of counts reported by real hardware, from darkness to sunlight.
+            #[allow(clippy::cast_possible_truncation)]
+            let range = (u32::from(self.light_level) * (0xe8 - 0x50) / 255) as u8;
+            self.threshold = 0xe8 - range;
+            self.counter = 0;
+        } else if !self.clock && clock {
+            self.counter = self.counter.saturating_add(1);
+        }
+        self.clock = clock;
+    }
+}
+
+// GPIO pins used by the gyro sensor.
+const GYRO_PIN_START: usize = 0;
+const GYRO_PIN_CLOCK: usize = 1;
+pub(super) const GYRO_PIN_DATA: usize = 2;
+
+/// A rotation sensor, which measures angular velocity around the axis perpendicular to the
+/// screen.
+#[derive(Default, Debug)]
+pub struct Gyro {
+    /// Angular velocity, positive for clockwise rotation.
+    pub rotation: i16,
+    sample: u16,
+    clock: bool,
+    data: bool,
+}
+
+impl Gyro {
+    pub(super) fn data(&self) -> bool {
+        self.data
+    }
+
+    pub(super) fn write_pins(&mut self, pins: u8) {
+        if pins.bit(GYRO_PIN_START) {
+            // 0x6c0 is the resting value.
+            #[allow(clippy::cast_sign_loss)]
+            let sample = (0x6c0 + (self.rotation >> 5)) as u16;
+            self.sample = sample;
+        }
+
+        // Bits of the sample are shifted out MSB first on the falling edge of the clock.
+        let clock = pins.bit(GYRO_PIN_CLOCK);
+        if self.clock && !clock {
+            self.data = self.sample.bit(15);
+            self.sample <<= 1;
+        }
+        self.clock = clock;
+    }
+}
+
+const RUMBLE_PIN: usize = 3;
+
+#[derive(Default, Debug)]
+pub struct Rumble {
+    active: bool,
+}
+
+impl Rumble {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub(super) fn write_pins(&mut self, pins: u8) {
+        self.active = pins.bit(RUMBLE_PIN);
+    }
+}
+
+/// The two-axis accelerometer used by Yoshi Topsy-Turvy and Koro Koro Puzzle, which is mapped
+/// into the SRAM region at `0x0e00_8000..=0x0e00_85ff`.
+#[derive(Default, Debug)]
+pub struct TiltSensor {
+    /// Tilt to the right, relative to being held level.
+    pub x: i16,
+    /// Tilt towards the player, relative to being held level.
+    pub y: i16,
+    sample: (u16, u16),
+    unlocked: bool,
+    ready: bool,
+}
+
+impl TiltSensor {
+    /// Returns `None` if `offset` (relative to the start of SRAM) is not a sensor register.
+    pub fn read(&self, offset: u32) -> Option<u8> {
+        #[allow(clippy::cast_possible_truncation)]
+        let value = match offset {
+            0x8200 => self.sample.0 as u8,
+            0x8300 => (self.sample.0.bits(8..12) as u8).with_bit(7, self.ready),
+            0x8400 => self.sample.1 as u8,
+            0x8500 => self.sample.1.bits(8..12) as u8,
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    /// Returns false if `offset` is not a sensor register.
+    pub fn write(&mut self, offset: u32, value: u8) -> bool {
+        match offset {
+            0x8000 => self.unlocked = value == 0x55,
+            0x8100 => {
+                if self.unlocked && value == 0xaa {
+                    // 0x3a0 is the resting value.
+                    #[allow(clippy::cast_sign_loss)]
+                    let sample = |tilt: i16| (0x3a0 - (tilt >> 5)) as u16;
+                    self.sample = (sample(self.x), sample(self.y));
+                    self.ready = true;
+                }
+                self.unlocked = false;
+            }
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solar_sensor_works() {
+        let count_pulses = |light_level| {
+            let mut solar = SolarSensor {
+                light_level,
+                ..SolarSensor::default()
+            };
+            solar.write_pins(0b0010);
+            solar.write_pins(0b0000);
+
+            let mut pulses = 0;
+            while !solar.flag() {
+                solar.write_pins(0b0001);
+                solar.write_pins(0b0000);
+                pulses += 1;
+            }
+
+            pulses
+        };
+
+        assert_eq!(0xe8, count_pulses(0));
+        assert_eq!(0x50, count_pulses(255));
+        assert!(count_pulses(100) > count_pulses(200));
+    }
+
+    #[test]
+    fn gyro_works() {
+        let mut gyro = Gyro {
+            rotation: -0x1000,
+            ..Gyro::default()
+        };
+        gyro.write_pins(0b011);
+        gyro.write_pins(0b010);
+
+        let mut sample = 0u16;
+        for _ in 0..16 {
+            gyro.write_pins(0b000);
+            sample = (sample << 1) | u16::from(gyro.data());
+            gyro.write_pins(0b010);
+        }
+        assert_eq!(0x6c0 - 0x80, sample);
+    }
+
+    #[test]
+    fn tilt_sensor_works() {
+        let mut tilt = TiltSensor {
+            x: 0x2000,
+            y: -0x2000,
+            ..TiltSensor::default()
+        };
+        // Sampling requires the unlock sequence to be written first.
+        assert!(tilt.write(0x8100, 0xaa));
+        assert_eq!(Some(0), tilt.read(0x8300));
+
+        assert!(tilt.write(0x8000, 0x55));
+        assert!(tilt.write(0x8100, 0xaa));
+        assert_eq!(Some(0xa0), tilt.read(0x8200));
+        assert_eq!(Some(0x80 | 0x2), tilt.read(0x8300));
+        assert_eq!(Some(0xa0), tilt.read(0x8400));
+        assert_eq!(Some(0x4), tilt.read(0x8500));
+        assert_eq!(None, tilt.read(0x8600));
+        assert!(!tilt.write(0x8600, 0));
+    }
+}
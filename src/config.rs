@@ -0,0 +1,308 @@
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    upscale::Upscaler,
+    video::{ColourProfile, FRAME_HEIGHT, FRAME_WIDTH},
+};
+
+/// How the screen is fitted to the window.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ScalingMode {
+    /// Stretched to fill the window.
+    Stretch,
+    /// Scaled as large as possible while keeping its aspect ratio, with black bars around it.
+    Aspect,
+    /// Like `Aspect`, but only scaled by whole multiples, so that all dots are the same size.
+    Integer,
+}
+
+impl ScalingMode {
+    /// Returns the area of a `width` x `height` window to draw the screen to, as x, y, width and
+    /// height, or `None` to fill the window.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss
+    )]
+    pub fn viewport(self, width: u32, height: u32) -> Option<(i32, i32, u32, u32)> {
+        let (frame_width, frame_height) = (FRAME_WIDTH as u32, FRAME_HEIGHT as u32);
+        let (view_width, view_height) = match self {
+            Self::Stretch => return None,
+            Self::Aspect => {
+                let scale = f64::min(
+                    f64::from(width) / f64::from(frame_width),
+                    f64::from(height) / f64::from(frame_height),
+                );
+
+                (
+                    (f64::from(frame_width) * scale).round() as u32,
+                    (f64::from(frame_height) * scale).round() as u32,
+                )
+            }
+            Self::Integer => {
+                let scale = (width / frame_width).min(height / frame_height).max(1);
+
+                (frame_width * scale, frame_height * scale)
+            }
+        };
+
+        // Centre the screen; it may not fit if the window is smaller than the integer scaled frame.
+        let x = (i64::from(width) - i64::from(view_width)) / 2;
+        let y = (i64::from(height) - i64::from(view_height)) / 2;
+
+        Some((x as i32, y as i32, view_width, view_height))
+    }
+}
+
+impl FromStr for ScalingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stretch" => Ok(Self::Stretch),
+            "aspect" => Ok(Self::Aspect),
+            "integer" => Ok(Self::Integer),
+            _ => Err(anyhow!("unknown scaling mode \"{s}\"")),
+        }
+    }
+}
+
+impl fmt::Display for ScalingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Stretch => "stretch",
+            Self::Aspect => "aspect",
+            Self::Integer => "integer",
+        })
+    }
+}
+
+/// Settings of the frontend that are saved between runs, stored as lines of "key = value".
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Config {
+    /// The initial size of the window, as a multiple of the frame size.
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub scaling: ScalingMode,
+    pub upscaler: Upscaler,
+    pub colour_profile: ColourProfile,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scale: 3,
+            fullscreen: false,
+            scaling: ScalingMode::Aspect,
+            upscaler: Upscaler::Nearest(1),
+            colour_profile: ColourProfile::Raw,
+        }
+    }
+}
+
+impl Config {
+    /// Returns the path of the config file in the user's config directory, if it could be found.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+
+        Some(dir.join("memetendo-unsafe-boy-advance").join("config"))
+    }
+
+    /// Loads the config from `path`, or returns the default config if it doesn't exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(s) => s.parse(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "scale" => {
+                self.scale = value.parse()?;
+                if self.scale == 0 {
+                    return Err(anyhow!("scale must be at least 1"));
+                }
+            }
+            "fullscreen" => {
+                self.fullscreen = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(anyhow!("expected true or false for \"{key}\"")),
+                };
+            }
+            "scaling" => self.scaling = value.parse()?,
+            "upscaler" => self.upscaler = value.parse()?,
+            "colour_profile" => self.colour_profile = value.parse()?,
+            // Skipped so that config files from other versions can still be loaded.
+            _ => eprintln!("warning: skipping unknown config key \"{key}\""),
+        }
+
+        Ok(())
+    }
+
+    /// Applies the settings that differ between `old` and `new`, leaving the others as they are.
+    pub fn apply_changes(&mut self, old: &Self, new: &Self) {
+        let Self {
+            scale,
+            fullscreen,
+            scaling,
+            upscaler,
+            colour_profile,
+        } = *new;
+        if scale != old.scale {
+            self.scale = scale;
+        }
+        if fullscreen != old.fullscreen {
+            self.fullscreen = fullscreen;
+        }
+        if scaling != old.scaling {
+            self.scaling = scaling;
+        }
+        if upscaler != old.upscaler {
+            self.upscaler = upscaler;
+        }
+        if colour_profile != old.colour_profile {
+            self.colour_profile = colour_profile;
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        Ok(fs::write(path, self.to_string())?)
+    }
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut config = Self::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| anyhow!("expected \"key = value\" on line {}", i + 1))?;
+            config
+                .set(key, value)
+                .with_context(|| format!("invalid config on line {}", i + 1))?;
+        }
+
+        Ok(config)
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "scale = {}", self.scale)?;
+        writeln!(f, "fullscreen = {}", self.fullscreen)?;
+        writeln!(f, "scaling = {}", self.scaling)?;
+        writeln!(f, "upscaler = {}", self.upscaler)?;
+        writeln!(f, "colour_profile = {}", self.colour_profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viewport_works() {
+        assert_eq!(None, ScalingMode::Stretch.viewport(1000, 300));
+        assert_eq!(
+            Some((275, 0, 450, 300)),
+            ScalingMode::Aspect.viewport(1000, 300)
+        );
+        assert_eq!(
+            Some((0, 26, 640, 427)),
+            ScalingMode::Aspect.viewport(640, 480)
+        );
+        assert_eq!(
+            Some((80, 80, 480, 320)),
+            ScalingMode::Integer.viewport(640, 480)
+        );
+        assert_eq!(
+            Some((0, 0, 240, 160)),
+            ScalingMode::Integer.viewport(240, 160)
+        );
+        assert_eq!(
+            Some((-20, -10, 240, 160)),
+            ScalingMode::Integer.viewport(200, 140)
+        );
+    }
+
+    #[test]
+    fn config_parse_works() {
+        let config = Config {
+            scale: 5,
+            fullscreen: true,
+            scaling: ScalingMode::Integer,
+            upscaler: Upscaler::Xbrz(4),
+            colour_profile: ColourProfile::GbaSp,
+        };
+        assert_eq!(config, config.to_string().parse().unwrap());
+
+        let config: Config = "\n# Comment\n  scaling=stretch  \nscale = 2\n"
+            .parse()
+            .unwrap();
+        assert_eq!(2, config.scale);
+        assert_eq!(ScalingMode::Stretch, config.scaling);
+        assert!(!config.fullscreen);
+        assert_eq!(ColourProfile::Raw, config.colour_profile);
+
+        assert!("scale = 0".parse::<Config>().is_err());
+        assert!("fullscreen = yes".parse::<Config>().is_err());
+        assert!("upscaler".parse::<Config>().is_err());
+        assert!("colour_profile = sepia".parse::<Config>().is_err());
+        assert_eq!(Config::default(), "volume = 11".parse::<Config>().unwrap());
+    }
+
+    #[test]
+    fn apply_changes_works() {
+        // Settings given as options, which shouldn't be saved
+        let saved = Config::default();
+        let options = Config {
+            scale: 5,
+            upscaler: Upscaler::Xbrz(3),
+            ..saved
+        };
+
+        // Only the settings changed while running are applied
+        let mut changed = saved;
+        changed.apply_changes(&options, &options);
+        assert_eq!(saved, changed);
+
+        let running = Config {
+            fullscreen: true,
+            upscaler: Upscaler::Grid(4),
+            ..options
+        };
+        changed.apply_changes(&options, &running);
+        assert_eq!(
+            Config {
+                fullscreen: true,
+                upscaler: Upscaler::Grid(4),
+                ..saved
+            },
+            changed
+        );
+    }
+}
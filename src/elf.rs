@@ -0,0 +1,379 @@
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+const ROM_ADDRS: Range<u32> = 0x0800_0000..0x0a00_0000;
+const EWRAM_ADDRS: Range<u32> = 0x0200_0000..0x0204_0000;
+const IWRAM_ADDRS: Range<u32> = 0x0300_0000..0x0300_8000;
+
+const EM_ARM: u16 = 40;
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(b"\x7fELF")
+}
+
+#[derive(Debug)]
+pub struct Segment {
+    pub addr: u32,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    fn contains(&self, addr: u32) -> bool {
+        // Segments are checked to fit in memory, so the end can't overflow.
+        #[allow(clippy::cast_possible_truncation)]
+        let end = self.addr + self.data.len() as u32;
+
+        (self.addr..end).contains(&addr)
+    }
+}
+
+#[derive(Debug)]
+pub struct Symbol {
+    pub addr: u32,
+    pub size: u32,
+    pub name: String,
+}
+
+/// Function and object symbols, sorted by address.
+#[derive(Default, Debug)]
+pub struct SymbolTable(Vec<Symbol>);
+
+impl SymbolTable {
+    pub fn new(mut symbols: Vec<Symbol>) -> Self {
+        symbols.sort_by_key(|sym| sym.addr);
+
+        Self(symbols)
+    }
+
+    /// Returns the symbol containing `addr`, and the offset of `addr` within it. Symbols without a
+    /// size are assumed to extend until the next symbol in the same memory region.
+    pub fn lookup(&self, addr: u32) -> Option<(&Symbol, u32)> {
+        let idx = self
+            .0
+            .partition_point(|sym| sym.addr <= addr)
+            .checked_sub(1)?;
+        let sym = &self.0[idx];
+        let offset = addr - sym.addr;
+
+        let contains = if sym.size == 0 {
+            sym.addr >> 24 == addr >> 24
+        } else {
+            offset < sym.size
+        };
+
+        contains.then_some((sym, offset))
+    }
+
+    /// Returns the symbol named `name`.
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.0.iter().find(|sym| sym.name == name)
+    }
+
+    /// Formats `addr` like "main+0x1c", or just as a hex address if no symbol contains it.
+    pub fn describe(&self, addr: u32) -> String {
+        match self.lookup(addr) {
+            Some((sym, 0)) => sym.name.clone(),
+            Some((sym, offset)) => format!("{}+{offset:#x}", sym.name),
+            None => format!("{addr:#010x}"),
+        }
+    }
+}
+
+/// An ELF32 ARM executable, such as those produced by devkitARM.
+#[derive(Debug)]
+pub struct Elf {
+    /// The address to start executing from, which has bit 0 set if it's THUMB code.
+    pub entry: u32,
+    pub segments: Vec<Segment>,
+    pub symbols: SymbolTable,
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.0.get(offset..end))
+            .ok_or_else(|| anyhow!("ELF file is truncated"))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(offset, 2)?.try_into()?))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(offset, 4)?.try_into()?))
+    }
+
+    fn usize(&self, offset: usize) -> Result<usize> {
+        Ok(self.u32(offset)? as usize)
+    }
+
+    fn c_str(&self, offset: usize) -> Result<&str> {
+        let bytes = self.0.get(offset..).unwrap_or_default();
+        let len = bytes
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| anyhow!("ELF file has an unterminated string"))?;
+
+        std::str::from_utf8(&bytes[..len]).context("ELF file has an invalid string")
+    }
+}
+
+impl Elf {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let r = Reader(data);
+        if !is_elf(data) {
+            bail!("not an ELF file");
+        }
+        if r.bytes(4, 2)? != [1, 1] {
+            bail!("ELF file is not 32-bit little-endian");
+        }
+        if r.u16(18)? != EM_ARM {
+            bail!("ELF file is not for ARM");
+        }
+
+        let entry = r.u32(24)?;
+        let (ph_off, ph_size, ph_num) = (r.usize(28)?, r.u16(42)?, r.u16(44)?);
+        let (sh_off, sh_size, sh_num) = (r.usize(32)?, r.u16(46)?, r.u16(48)?);
+        let (ph_size, ph_num) = (usize::from(ph_size), usize::from(ph_num));
+        let (sh_size, sh_num) = (usize::from(sh_size), usize::from(sh_num));
+
+        let mut segments = Vec::new();
+        for ph in (0..ph_num).map(|i: usize| ph_off + i * ph_size) {
+            if r.u32(ph)? != PT_LOAD {
+                continue;
+            }
+
+            // Load segments at their physical address, as sections copied to RAM by the startup
+            // code (like .iwram) are stored in ROM.
+            let (offset, addr, file_len, mem_len) = (
+                r.usize(ph + 4)?,
+                r.u32(ph + 12)?,
+                r.usize(ph + 16)?,
+                r.usize(ph + 20)?,
+            );
+            if mem_len == 0 {
+                continue;
+            }
+
+            // Check the segment fits before allocating it, as its size may be bogus.
+            let len = mem_len.max(file_len);
+            let fits = u32::try_from(len)
+                .ok()
+                .and_then(|len| addr.checked_add(len))
+                .is_some_and(|end| {
+                    [ROM_ADDRS, EWRAM_ADDRS, IWRAM_ADDRS]
+                        .iter()
+                        .any(|range| range.contains(&addr) && end <= range.end)
+                });
+            if !fits {
+                bail!("ELF segment at {addr:#010x} of size {len:#x} is outside of ROM and RAM");
+            }
+
+            let mut data = r.bytes(offset, file_len)?.to_vec();
+            data.resize(len, 0);
+            segments.push(Segment { addr, data });
+        }
+
+        // Bit 0 is set for a THUMB entry point, otherwise it must be word-aligned ARM code.
+        if entry & 1 == 0 && entry & 0b10 != 0 {
+            bail!("ELF entry point {entry:#010x} is misaligned");
+        }
+        if !segments.iter().any(|seg| seg.contains(entry & !1)) {
+            bail!("ELF entry point {entry:#010x} is outside of the loaded segments");
+        }
+
+        let mut symbols = Vec::new();
+        for sh in (0..sh_num).map(|i: usize| sh_off + i * sh_size) {
+            if r.u32(sh + 4)? != SHT_SYMTAB {
+                continue;
+            }
+
+            let (offset, len, entry_len) =
+                (r.usize(sh + 16)?, r.usize(sh + 20)?, r.usize(sh + 36)?);
+            let str_sh = sh_off + r.usize(sh + 24)? * sh_size;
+            let str_offset = r.usize(str_sh + 16)?;
+            if entry_len == 0 {
+                bail!("ELF symbol table has an invalid entry size");
+            }
+
+            for sym in (offset..offset + len).step_by(entry_len) {
+                let kind = r.bytes(sym + 12, 1)?[0] & 0xf;
+                if kind != STT_FUNC && kind != STT_OBJECT {
+                    continue;
+                }
+
+                let name = r.c_str(str_offset + r.usize(sym)?)?;
+                if name.is_empty() {
+                    continue;
+                }
+
+                let mut addr = r.u32(sym + 4)?;
+                if kind == STT_FUNC {
+                    // Bit 0 is set for THUMB functions.
+                    addr &= !1;
+                }
+
+                symbols.push(Symbol {
+                    addr,
+                    size: r.u32(sym + 8)?,
+                    name: name.to_string(),
+                });
+            }
+        }
+
+        Ok(Self {
+            entry,
+            segments,
+            symbols: SymbolTable::new(symbols),
+        })
+    }
+
+    /// Returns a ROM image containing the segments that are loaded into ROM.
+    pub fn rom_image(&self) -> Vec<u8> {
+        let mut rom = Vec::new();
+        for seg in self
+            .segments
+            .iter()
+            .filter(|seg| ROM_ADDRS.contains(&seg.addr))
+        {
+            let start = (seg.addr - ROM_ADDRS.start) as usize;
+            let end = start + seg.data.len();
+            if rom.len() < end {
+                rom.resize(end, 0);
+            }
+
+            rom[start..end].copy_from_slice(&seg.data);
+        }
+
+        rom
+    }
+
+    /// Returns the segments that are loaded into RAM.
+    pub fn ram_segments(&self) -> impl Iterator<Item = &Segment> {
+        self.segments
+            .iter()
+            .filter(|seg| !ROM_ADDRS.contains(&seg.addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+        buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+        buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Builds an ELF with a ROM and an IWRAM segment, and a symbol table.
+    fn make_elf() -> Vec<u8> {
+        let mut elf = vec![0; 0x400];
+        elf[..6].copy_from_slice(b"\x7fELF\x01\x01");
+        put_u16(&mut elf, 18, EM_ARM);
+        put_u32(&mut elf, 24, 0x0800_0000);
+        put_u32(&mut elf, 28, 0x40); // e_phoff
+        put_u32(&mut elf, 32, 0x100); // e_shoff
+        put_u16(&mut elf, 42, 32); // e_phentsize
+        put_u16(&mut elf, 44, 2); // e_phnum
+        put_u16(&mut elf, 46, 40); // e_shentsize
+        put_u16(&mut elf, 48, 3); // e_shnum
+
+        // ROM segment
+        put_u32(&mut elf, 0x40, PT_LOAD);
+        put_u32(&mut elf, 0x44, 0x200);
+        put_u32(&mut elf, 0x4c, 0x0800_0000);
+        put_u32(&mut elf, 0x50, 4);
+        put_u32(&mut elf, 0x54, 4);
+        elf[0x200..0x204].copy_from_slice(&[1, 2, 3, 4]);
+
+        // IWRAM segment, zero-filled past its file data
+        put_u32(&mut elf, 0x60, PT_LOAD);
+        put_u32(&mut elf, 0x64, 0x204);
+        put_u32(&mut elf, 0x6c, 0x0300_0000);
+        put_u32(&mut elf, 0x70, 2);
+        put_u32(&mut elf, 0x74, 4);
+        elf[0x204..0x206].copy_from_slice(&[5, 6]);
+
+        // Section 1: symbol table, linked to the string table in section 2
+        put_u32(&mut elf, 0x128 + 4, SHT_SYMTAB);
+        put_u32(&mut elf, 0x128 + 16, 0x300);
+        put_u32(&mut elf, 0x128 + 20, 3 * 16);
+        put_u32(&mut elf, 0x128 + 24, 2);
+        put_u32(&mut elf, 0x128 + 36, 16);
+        put_u32(&mut elf, 0x150 + 16, 0x380);
+        elf[0x380..0x391].copy_from_slice(b"\0main\0buf\0$t\0foo\0");
+
+        // Symbols: a THUMB function, an object, and a mapping symbol that should be skipped.
+        put_u32(&mut elf, 0x310, 1);
+        put_u32(&mut elf, 0x314, 0x0800_0011);
+        put_u32(&mut elf, 0x318, 0x10);
+        elf[0x31c] = STT_FUNC;
+        put_u32(&mut elf, 0x320, 6);
+        put_u32(&mut elf, 0x324, 0x0300_0000);
+        elf[0x32c] = STT_OBJECT;
+        put_u32(&mut elf, 0x300, 10);
+        put_u32(&mut elf, 0x304, 0x0800_0000);
+
+        elf
+    }
+
+    #[test]
+    fn parse_works() {
+        let elf = Elf::parse(&make_elf()).unwrap();
+        assert_eq!(0x0800_0000, elf.entry);
+        assert_eq!([1, 2, 3, 4], elf.rom_image()[..]);
+
+        let ram_segments: Vec<_> = elf.ram_segments().collect();
+        assert_eq!(1, ram_segments.len());
+        assert_eq!(0x0300_0000, ram_segments[0].addr);
+        assert_eq!([5, 6, 0, 0], ram_segments[0].data[..]);
+
+        assert_eq!("main", elf.symbols.describe(0x0800_0010));
+        assert_eq!("main+0xe", elf.symbols.describe(0x0800_001e));
+        assert_eq!("0x08000020", elf.symbols.describe(0x0800_0020));
+        assert_eq!("buf+0x4", elf.symbols.describe(0x0300_0004));
+        assert_eq!("0x08000000", elf.symbols.describe(0x0800_0000));
+
+        assert_eq!(0x0800_0010, elf.symbols.find("main").unwrap().addr);
+        assert!(elf.symbols.find("foo").is_none());
+    }
+
+    #[test]
+    fn parse_errors_work() {
+        let mut elf = make_elf();
+        elf[5] = 2;
+        assert!(Elf::parse(&elf).is_err());
+
+        let mut elf = make_elf();
+        put_u32(&mut elf, 0x6c, 0x0400_0000);
+        assert!(Elf::parse(&elf).is_err());
+
+        assert!(Elf::parse(&make_elf()[..0x100]).is_err());
+
+        // A huge segment is rejected before it's allocated
+        let mut elf = make_elf();
+        put_u32(&mut elf, 0x74, u32::MAX);
+        assert!(Elf::parse(&elf).is_err());
+
+        // Entry points must be aligned and inside a segment
+        for entry in [0x0800_0002, 0x0800_0004, 0x0300_0005, 0x0600_0000] {
+            let mut elf = make_elf();
+            put_u32(&mut elf, 24, entry);
+            assert!(Elf::parse(&elf).is_err(), "{entry:#x}");
+        }
+        let mut elf = make_elf();
+        put_u32(&mut elf, 24, 0x0300_0003);
+        assert_eq!(0x0300_0003, Elf::parse(&elf).unwrap().entry);
+    }
+}
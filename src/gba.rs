@@ -4,7 +4,9 @@ use crate::{
     arm7tdmi::Cpu,
     bus::{self, Bus, BusMut},
     cart::{Bios, Cartridge},
-    video::{Screen, VideoController},
+    elf::Elf,
+    multiboot,
+    video::{ColourProfile, FrameBlendMode, Screen, VideoController, VideoMemory},
 };
 
 pub struct Gba<'a, 'b> {
@@ -48,16 +50,84 @@ impl<'a, 'b> Gba<'a, 'b> {
     }
 
     pub fn reset_and_skip_bios(&mut self) {
+        self.reset_and_skip_bios_to(0x0800_0000);
+    }
+
+    fn reset_and_skip_bios_to(&mut self, entry_addr: u32) {
+        self.reset_for_skipped_bios();
+        self.skip_bios_to(entry_addr);
+    }
+
+    /// Resets, then clears the top of IWRAM like the BIOS does before it starts the cartridge.
+    fn reset_for_skipped_bios(&mut self) {
         self.reset();
+        self.iwram[0x7e00..].fill(0);
+    }
+
+    fn skip_bios_to(&mut self, entry_addr: u32) {
         let bus = &bus!(self);
-        self.cpu.skip_bios(bus);
+        self.cpu.skip_bios(bus, entry_addr);
+    }
 
-        self.iwram[0x7e00..].fill(0);
+    /// Copies a multiboot image into EWRAM and starts executing it, skipping the BIOS.
+    ///
+    /// If `as_slave` is set, the header fields that the BIOS fills in after a successful transfer
+    /// are also written, as if the image was received as the first slave in multiplay mode. The
+    /// transfer itself isn't emulated, so the serial registers are left as they are after a reset.
+    pub fn reset_and_boot_multiboot(&mut self, image: &[u8], as_slave: bool) {
+        let len = image.len().min(multiboot::MAX_LEN);
+        self.ewram[..len].copy_from_slice(&image[..len]);
+        self.ewram[len..].fill(0);
+
+        if as_slave {
+            self.ewram[multiboot::BOOT_MODE_OFFSET] = 3;
+            self.ewram[multiboot::SLAVE_ID_OFFSET] = 1;
+        }
+
+        self.reset_and_skip_bios_to(multiboot::ENTRY_ADDR);
+    }
+
+    /// Copies the RAM segments of an ELF executable into memory and starts executing it from its
+    /// entry point, skipping the BIOS. The ROM segments should be loaded as the cartridge.
+    pub fn reset_and_boot_elf(&mut self, elf: &Elf) {
+        // Load the segments after clearing IWRAM, but before the entry point is fetched.
+        self.reset_for_skipped_bios();
+        let mut bus = bus!(self);
+        for seg in elf.ram_segments() {
+            for (addr, &value) in (seg.addr..).zip(&seg.data) {
+                bus.write_byte(addr, value);
+            }
+        }
+
+        self.skip_bios_to(elf.entry);
+    }
+
+    /// Composes scanlines on a background thread, so drawing doesn't compete with the CPU.
+    pub fn enable_video_thread(&mut self) {
+        self.video.enable_render_thread();
+    }
+
+    pub fn set_colour_profile(&mut self, profile: ColourProfile) {
+        self.video.set_colour_profile(profile);
+    }
+
+    /// Blends consecutive frames together before presenting them, or not if `mode` is `None`.
+    pub fn set_frame_blend_mode(&mut self, mode: Option<FrameBlendMode>) {
+        self.video.set_frame_blend_mode(mode);
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cart_mut(&mut self) -> &mut Cartridge {
+        self.cart
     }
 
     pub fn step(&mut self, screen: &mut impl Screen) {
         self.cpu.step(&mut bus!(self));
         self.video.step(screen, &mut self.cpu, 8);
+        self.cart.step(&mut self.cpu, 8);
     }
 }
 
@@ -71,11 +141,12 @@ pub(super) struct GbaBus<'a> {
 
 impl GbaBus<'_> {
     fn read_rom(&self, addr: u32) -> u8 {
-        self.cart
-            .rom()
-            .get((addr & 0x01ff_ffff) as usize)
-            .copied()
-            .unwrap_or(0xff)
+        let offset = addr & 0x01ff_ffff;
+        if let Some(value) = self.cart.gpio.as_ref().and_then(|gpio| gpio.read(offset)) {
+            return value;
+        }
+
+        self.cart.read_rom(offset)
     }
 
     fn read_io(&self, addr: u32) -> u8 {
@@ -106,6 +177,18 @@ impl GbaBus<'_> {
             // BG3CNT
             0xe => self.video.bgcnt[3].lo_bits(),
             0xf => self.video.bgcnt[3].hi_bits(),
+            // WININ
+            0x48 => self.video.winin[0].bits(),
+            0x49 => self.video.winin[1].bits(),
+            // WINOUT
+            0x4a => self.video.winout.bits(),
+            0x4b => self.video.objwin.bits(),
+            // BLDCNT
+            0x50 => self.video.bldcnt.lo_bits(),
+            0x51 => self.video.bldcnt.hi_bits(),
+            // BLDALPHA
+            0x52 => self.video.bldalpha[0],
+            0x53 => self.video.bldalpha[1],
             _ => 0xff,
         }
     }
@@ -133,6 +216,55 @@ impl GbaBus<'_> {
             // BG3CNT
             0xe => self.video.bgcnt[3].set_lo_bits(value),
             0xf => self.video.bgcnt[3].set_hi_bits(value),
+            // BG0HOFS, BG0VOFS, BG1HOFS, ..., BG3VOFS
+            offset @ 0x10..=0x1f => {
+                let bg = (offset as usize - 0x10) / 4;
+                let scroll = if offset.bit(1) {
+                    &mut self.video.bgvofs[bg]
+                } else {
+                    &mut self.video.bghofs[bg]
+                };
+
+                if offset.bit(0) {
+                    scroll.set_bit(8, value.bit(0));
+                } else {
+                    scroll.set_bits(..8, value.into());
+                }
+            }
+            // BG2PA, BG2PB, BG2PC, BG2PD, BG2X, BG2Y, then the same for BG3
+            offset @ 0x20..=0x3f => {
+                self.video.bgaffine[(offset as usize - 0x20) / 0x10]
+                    .write_byte(offset & 0xf, value);
+            }
+            // WIN0H
+            0x40 => self.video.win_bounds[0].right = value,
+            0x41 => self.video.win_bounds[0].left = value,
+            // WIN1H
+            0x42 => self.video.win_bounds[1].right = value,
+            0x43 => self.video.win_bounds[1].left = value,
+            // WIN0V
+            0x44 => self.video.win_bounds[0].bottom = value,
+            0x45 => self.video.win_bounds[0].top = value,
+            // WIN1V
+            0x46 => self.video.win_bounds[1].bottom = value,
+            0x47 => self.video.win_bounds[1].top = value,
+            // WININ
+            0x48 => self.video.winin[0].set_bits(value),
+            0x49 => self.video.winin[1].set_bits(value),
+            // WINOUT
+            0x4a => self.video.winout.set_bits(value),
+            0x4b => self.video.objwin.set_bits(value),
+            // MOSAIC
+            0x4c => self.video.mosaic.set_lo_bits(value),
+            0x4d => self.video.mosaic.set_hi_bits(value),
+            // BLDCNT
+            0x50 => self.video.bldcnt.set_lo_bits(value),
+            0x51 => self.video.bldcnt.set_hi_bits(value),
+            // BLDALPHA
+            0x52 => self.video.bldalpha[0] = value.bits(..5),
+            0x53 => self.video.bldalpha[1] = value.bits(..5),
+            // BLDY
+            0x54 => self.video.bldy = value.bits(..5),
             _ => {}
         }
     }
@@ -160,7 +292,12 @@ impl Bus for GbaBus<'_> {
                 self.read_rom(addr)
             }
             // SRAM
-            0x0e00_0000..=0x0e00_ffff => self.cart.sram.as_ref().read_byte(addr & 0xffff),
+            0x0e00_0000..=0x0e00_ffff => {
+                let offset = addr & 0xffff;
+                let tilt_value = self.cart.tilt.as_ref().and_then(|tilt| tilt.read(offset));
+
+                tilt_value.unwrap_or_else(|| self.cart.sram.as_ref().read_byte(offset))
+            }
             // Unused
             _ => 0xff,
         }
@@ -169,6 +306,11 @@ impl Bus for GbaBus<'_> {
 
 impl BusMut for GbaBus<'_> {
     fn write_byte(&mut self, addr: u32, value: u8) {
+        // Draw the dots before this write using the old contents of the video registers and memory.
+        if matches!(addr, 0x0400_0000..=0x0400_0055 | 0x0500_0000..=0x07ff_ffff) {
+            self.video.catch_up();
+        }
+
         match addr {
             // External WRAM
             0x0200_0000..=0x02ff_ffff => self.ewram.as_mut().write_byte(addr & 0x3_ffff, value),
@@ -179,23 +321,36 @@ impl BusMut for GbaBus<'_> {
             // Palette RAM
             0x0500_0000..=0x05ff_ffff => {
                 // 8-bit writes act weird; write as a hword.
+                let value = u16::from_le_bytes([value, value]);
                 self.video
-                    .palette_ram
-                    .as_mut()
-                    .write_hword(addr & 0x3ff, u16::from_le_bytes([value, value]));
+                    .write_hword(VideoMemory::Palette, addr & 0x3ff, value);
             }
             // VRAM
             0x0600_0000..=0x06ff_ffff => {
                 // Like palette RAM, but only write a hword for BG data.
                 if (addr as usize & 0x1_7fff) < self.video.dispcnt.obj_vram_offset() {
+                    let value = u16::from_le_bytes([value, value]);
                     self.video
-                        .vram
-                        .as_mut()
-                        .write_hword(addr & 0x1_7fff, u16::from_le_bytes([value, value]));
+                        .write_hword(VideoMemory::Vram, addr & 0x1_7fff, value);
+                }
+            }
+            // ROM Mirror; only the GPIO port is writable
+            0x0800_0000..=0x0dff_ffff => {
+                if let Some(gpio) = &mut self.cart.gpio {
+                    gpio.write(addr & 0x01ff_ffff, value);
                 }
             }
             // SRAM
-            0x0e00_0000..=0x0e00_ffff => self.cart.sram.as_mut().write_byte(addr & 0xffff, value),
+            0x0e00_0000..=0x0e00_ffff => {
+                let offset = addr & 0xffff;
+                if let Some(tilt) = &mut self.cart.tilt {
+                    if tilt.write(offset, value) {
+                        return;
+                    }
+                }
+
+                self.cart.sram.as_mut().write_byte(offset, value);
+            }
             // Read-only, Unused, Ignored 8-bit writes to OAM/VRAM
             _ => {}
         }
@@ -204,20 +359,26 @@ impl BusMut for GbaBus<'_> {
     fn write_hword(&mut self, addr: u32, value: u16) {
         // Video memory has weird behaviour when writing 8-bit values, so we can't simply delegate
         // such writes to write_hword_as_bytes.
+        if (0x0500_0000..=0x07ff_ffff).contains(&addr) {
+            self.video.catch_up();
+        }
+
         match addr {
             // Palette RAM
             0x0500_0000..=0x05ff_ffff => {
                 self.video
-                    .palette_ram
-                    .as_mut()
-                    .write_hword(addr & 0x3ff, value);
+                    .write_hword(VideoMemory::Palette, addr & 0x3ff, value);
             }
             // VRAM
             0x0600_0000..=0x06ff_ffff => {
-                self.video.vram.as_mut().write_hword(addr & 0x1_7fff, value);
+                self.video
+                    .write_hword(VideoMemory::Vram, addr & 0x1_7fff, value);
             }
             // OAM
-            0x0700_0000..=0x07ff_ffff => self.video.oam.as_mut().write_hword(addr & 0x3ff, value),
+            0x0700_0000..=0x07ff_ffff => {
+                self.video
+                    .write_hword(VideoMemory::Oam, addr & 0x3ff, value);
+            }
             _ => bus::write_hword_as_bytes(self, addr, value),
         }
     }
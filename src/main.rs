@@ -3,40 +3,59 @@
 mod arm7tdmi;
 mod bus;
 mod cart;
+mod config;
+mod elf;
 mod gba;
+mod multiboot;
+mod screenshot;
+mod upscale;
 mod util;
 mod video;
 
 use std::{
-    path::Path,
+    fmt::Write,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    thread,
     time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context, Result};
+use arm7tdmi::Cpu;
 use cart::Cartridge;
-use clap::{arg, command};
+use clap::{arg, command, ArgMatches};
+use config::{Config, ScalingMode};
+use elf::{Elf, SymbolTable};
 use gba::Gba;
 use sdl2::{
+    controller::{Axis, GameController},
     event::Event,
+    keyboard::{Keycode, Mod, Scancode},
     pixels::{Color, PixelFormatEnum},
+    rect::Rect,
     render::{Texture, TextureCreator, WindowCanvas},
-    video::WindowContext,
-    EventPump, Sdl, VideoSubsystem,
+    video::{FullscreenType, WindowContext},
+    EventPump, GameControllerSubsystem, Sdl, VideoSubsystem,
 };
-use video::{FrameBuffer, Screen, FRAME_HEIGHT, FRAME_WIDTH};
+use upscale::Upscaler;
+use video::{FrameBlendMode, FrameBuffer, Screen, FRAME_HEIGHT, FRAME_WIDTH};
 
-use crate::cart::Bios;
+use crate::cart::{
+    rtc::{DateTime, TimeSource},
+    Bios,
+};
 
 struct SdlContext {
     sdl: Sdl,
     sdl_video: VideoSubsystem,
+    sdl_controller: GameControllerSubsystem,
     win_canvas: WindowCanvas,
     win_texture_creator: TextureCreator<WindowContext>,
     event_pump: EventPump,
 }
 
 impl SdlContext {
-    fn init() -> Result<Self> {
+    fn init(config: &Config) -> Result<Self> {
         let sdl = sdl2::init().map_err(|e| anyhow!("failed to init sdl2: {e}"))?;
 
         let event_pump = sdl
@@ -47,17 +66,26 @@ impl SdlContext {
             .video()
             .map_err(|e| anyhow!("failed to init sdl2 video subsystem: {e}"))?;
 
+        let sdl_controller = sdl
+            .game_controller()
+            .map_err(|e| anyhow!("failed to init sdl2 game controller subsystem: {e}"))?;
+
         #[allow(clippy::cast_possible_truncation)]
-        let window = sdl_video
+        let mut window = sdl_video
             .window(
-                "Memetendo Unsafe Boy Advance",
-                FRAME_WIDTH as u32,
-                FRAME_HEIGHT as u32,
+                &window_title(config.upscaler),
+                FRAME_WIDTH as u32 * config.scale,
+                FRAME_HEIGHT as u32 * config.scale,
             )
             .position_centered()
             .resizable()
             .build()
             .context("failed to create sdl2 window")?;
+        if config.fullscreen {
+            window
+                .set_fullscreen(FullscreenType::Desktop)
+                .map_err(|e| anyhow!("failed to enter fullscreen: {e}"))?;
+        }
 
         let win_canvas = window
             .into_canvas()
@@ -69,6 +97,7 @@ impl SdlContext {
         Ok(Self {
             sdl,
             sdl_video,
+            sdl_controller,
             win_canvas,
             win_texture_creator,
             event_pump,
@@ -78,37 +107,79 @@ impl SdlContext {
 
 struct SdlScreen<'r> {
     frame_buf: FrameBuffer,
+    upscaler: Upscaler,
+    /// The upscaled frame.
+    scaled_buf: Vec<u32>,
     texture_is_stale: bool,
+    texture_creator: &'r TextureCreator<WindowContext>,
     texture: Texture<'r>,
 }
 
 impl<'r> SdlScreen<'r> {
-    fn new<T>(texture_creator: &'r TextureCreator<T>) -> Result<Self> {
+    fn new(texture_creator: &'r TextureCreator<WindowContext>, upscaler: Upscaler) -> Result<Self> {
+        Ok(Self {
+            frame_buf: FrameBuffer::default(),
+            upscaler,
+            scaled_buf: Vec::new(),
+            texture_is_stale: true,
+            texture_creator,
+            texture: Self::create_texture(texture_creator, upscaler)?,
+        })
+    }
+
+    fn create_texture(
+        texture_creator: &'r TextureCreator<WindowContext>,
+        upscaler: Upscaler,
+    ) -> Result<Texture<'r>> {
+        let factor = upscaler.factor();
+
         #[allow(clippy::cast_possible_truncation)]
-        let texture = texture_creator
+        texture_creator
             .create_texture_streaming(
                 PixelFormatEnum::RGB24,
-                FRAME_WIDTH as u32,
-                FRAME_HEIGHT as u32,
+                (FRAME_WIDTH * factor) as u32,
+                (FRAME_HEIGHT * factor) as u32,
             )
-            .context("failed to create screen texture")?;
+            .context("failed to create screen texture")
+    }
 
-        Ok(Self {
-            frame_buf: FrameBuffer::default(),
-            texture_is_stale: true,
-            texture,
-        })
+    fn set_upscaler(&mut self, upscaler: Upscaler) -> Result<()> {
+        if upscaler.factor() != self.upscaler.factor() {
+            self.texture = Self::create_texture(self.texture_creator, upscaler)?;
+        }
+        self.upscaler = upscaler;
+        self.texture_is_stale = true;
+
+        Ok(())
+    }
+
+    /// Switches to the next of the upscaler presets.
+    fn cycle_upscaler(&mut self) -> Result<()> {
+        let presets = Upscaler::PRESETS;
+        let next = presets
+            .iter()
+            .position(|&upscaler| upscaler == self.upscaler)
+            .map_or(0, |i| (i + 1) % presets.len());
+        self.set_upscaler(presets[next])
     }
 
     fn get_texture(&mut self) -> Result<&Texture> {
         if self.texture_is_stale {
+            self.upscaler.upscale(
+                &self.frame_buf.0,
+                FRAME_WIDTH,
+                FRAME_HEIGHT,
+                &mut self.scaled_buf,
+            );
+
+            let width = FRAME_WIDTH * self.upscaler.factor();
+            let scaled_buf = &self.scaled_buf;
             self.texture
                 .with_lock(None, |buf, pitch| {
-                    for y in 0..FRAME_HEIGHT {
-                        for x in 0..FRAME_WIDTH {
-                            let rgb = &self.frame_buf[(x, y)].to_le_bytes()[..3];
+                    for (y, row) in scaled_buf.chunks(width).enumerate() {
+                        for (x, colour) in row.iter().enumerate() {
                             let offset = y * pitch + x * 3;
-                            buf[offset..offset + 3].copy_from_slice(rgb);
+                            buf[offset..offset + 3].copy_from_slice(&colour.to_le_bytes()[..3]);
                         }
                     }
                 })
@@ -121,6 +192,129 @@ impl<'r> SdlScreen<'r> {
     }
 }
 
+/// Returns the title of the window, which shows the upscaler being used.
+fn window_title(upscaler: Upscaler) -> String {
+    format!("Memetendo Unsafe Boy Advance ({upscaler})")
+}
+
+fn is_fullscreen(canvas: &WindowCanvas) -> bool {
+    canvas.window().fullscreen_state() != FullscreenType::Off
+}
+
+fn toggle_fullscreen(canvas: &mut WindowCanvas) -> Result<()> {
+    let state = if is_fullscreen(canvas) {
+        FullscreenType::Off
+    } else {
+        FullscreenType::Desktop
+    };
+
+    canvas
+        .window_mut()
+        .set_fullscreen(state)
+        .map_err(|e| anyhow!("failed to toggle fullscreen: {e}"))
+}
+
+/// Switches the screen to the next upscaler preset, showing it in the window title.
+fn cycle_upscaler(canvas: &mut WindowCanvas, screen: &mut SdlScreen) -> Result<()> {
+    screen.cycle_upscaler()?;
+
+    canvas
+        .window_mut()
+        .set_title(&window_title(screen.upscaler))
+        .context("failed to set window title")
+}
+
+/// Saves the current frame, as it's shown on the screen, to the next free screenshot path.
+fn save_screenshot(screen: &SdlScreen) -> Result<()> {
+    let path = screenshot::next_path();
+    screenshot::save(&path, &screen.frame_buf, screen.upscaler)?;
+    eprintln!("saved screenshot to {}", path.display());
+
+    Ok(())
+}
+
+/// Draws the screen to the window, fitted according to `scaling`.
+fn draw_screen(
+    canvas: &mut WindowCanvas,
+    screen: &mut SdlScreen,
+    scaling: ScalingMode,
+) -> Result<()> {
+    let (width, height) = canvas
+        .output_size()
+        .map_err(|e| anyhow!("failed to get window size: {e}"))?;
+    let viewport = scaling
+        .viewport(width, height)
+        .map(|(x, y, width, height)| Rect::new(x, y, width, height));
+
+    canvas.clear();
+    canvas
+        .copy(screen.get_texture()?, None, viewport)
+        .map_err(|e| anyhow!("failed to draw screen texture: {e}"))?;
+    canvas.present();
+
+    Ok(())
+}
+
+struct CartInput {
+    controller: Option<GameController>,
+    light_level: u8,
+}
+
+impl CartInput {
+    fn handle_event(&mut self, context: &SdlContext, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } if self.controller.is_none() => {
+                self.controller = context.sdl_controller.open(which).ok();
+            }
+            Event::ControllerDeviceRemoved { which, .. }
+                if self.controller.as_ref().map(GameController::instance_id) == Some(which) =>
+            {
+                self.controller = None;
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Minus),
+                ..
+            } => self.light_level = self.light_level.saturating_sub(16),
+            Event::KeyDown {
+                keycode: Some(Keycode::Equals),
+                ..
+            } => self.light_level = self.light_level.saturating_add(16),
+            _ => {}
+        }
+    }
+
+    /// Feeds the keyboard and controller state to the cartridge's sensors, and the cartridge's
+    /// rumble state to the controller.
+    fn update_cart(&mut self, context: &SdlContext, cart: &mut Cartridge) {
+        let keys = context.event_pump.keyboard_state();
+        let axis =
+            |axis, neg, pos| match (keys.is_scancode_pressed(neg), keys.is_scancode_pressed(pos)) {
+                (true, false) => -i16::MAX,
+                (false, true) => i16::MAX,
+                _ => self.controller.as_ref().map_or(0, |c| c.axis(axis)),
+            };
+
+        if let Some(tilt) = &mut cart.tilt {
+            tilt.x = axis(Axis::LeftX, Scancode::J, Scancode::L);
+            tilt.y = axis(Axis::LeftY, Scancode::I, Scancode::K);
+        }
+
+        if let Some(gpio) = &mut cart.gpio {
+            if let Some(solar) = &mut gpio.solar {
+                solar.light_level = self.light_level;
+            }
+            if let Some(gyro) = &mut gpio.gyro {
+                gyro.rotation = axis(Axis::RightX, Scancode::U, Scancode::O);
+            }
+            if let (Some(rumble), Some(controller)) = (&gpio.rumble, &mut self.controller) {
+                let strength = if rumble.is_active() { u16::MAX } else { 0 };
+                // Not all controllers support rumble, so ignore errors.
+                let _ = controller.set_rumble(strength, strength, 100);
+            }
+        }
+    }
+}
+
 impl Screen for SdlScreen<'_> {
     fn present_frame(&mut self, frame_buf: &FrameBuffer) {
         self.frame_buf.0.copy_from_slice(&frame_buf.0[..]);
@@ -128,32 +322,355 @@ impl Screen for SdlScreen<'_> {
     }
 }
 
-fn main() -> Result<()> {
-    const REDRAW_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// Prints the function being executed whenever it changes, as named by the ELF symbol table.
+#[derive(Default)]
+struct CallTracer {
+    last_symbol: Option<String>,
+}
+
+impl CallTracer {
+    fn trace(&mut self, pc: u32, symbols: Option<&SymbolTable>) {
+        let symbol = symbols
+            .and_then(|symbols| symbols.lookup(pc))
+            .map(|(sym, _)| &sym.name);
+
+        if symbol != self.last_symbol.as_ref() {
+            let desc = symbols.map_or_else(|| format!("{pc:#010x}"), |s| s.describe(pc));
+            println!("{pc:08x}: {desc}");
+            self.last_symbol = symbol.cloned();
+        }
+    }
+}
+
+/// Debugging aids, which describe addresses using the ELF symbol table if there is one.
+struct Debugger<'a> {
+    symbols: Option<&'a SymbolTable>,
+    call_tracer: Option<CallTracer>,
+    /// Addresses to pause at, before the instructions there are executed.
+    breakpoints: Vec<u32>,
+}
+
+impl<'a> Debugger<'a> {
+    fn new(matches: &ArgMatches, symbols: Option<&'a SymbolTable>) -> Result<Self> {
+        let breakpoints = matches
+            .values_of("break")
+            .into_iter()
+            .flatten()
+            .map(|name| {
+                symbols
+                    .ok_or_else(|| anyhow!("breakpoints need an ELF file with a symbol table"))?
+                    .find(name)
+                    .map(|sym| sym.addr)
+                    .ok_or_else(|| anyhow!("no symbol named \"{name}\" to break at"))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            symbols,
+            call_tracer: matches.is_present("trace-calls").then(CallTracer::default),
+            breakpoints,
+        })
+    }
+
+    fn describe(&self, addr: u32) -> String {
+        self.symbols
+            .map_or_else(|| format!("{addr:#010x}"), |s| s.describe(addr))
+    }
+
+    /// Formats the CPU's registers, naming the functions that the PC and LR are in.
+    fn dump_cpu(&self, cpu: &Cpu) -> String {
+        let (pc, regs) = (cpu.pc(), cpu.registers());
+        let mut dump = format!(
+            "pc: {pc:08x} ({}), lr: {:08x} ({}), cpsr: {:08x}",
+            self.describe(pc),
+            regs[14],
+            self.describe(regs[14]),
+            cpu.cpsr()
+        );
+        for (i, value) in regs.iter().enumerate() {
+            let sep = if i % 4 == 0 { "\n" } else { ", " };
+            write!(dump, "{sep}r{i}: {value:08x}").unwrap();
+        }
+
+        dump
+    }
+
+    /// Called after each step of the CPU. Returns whether it stopped at a breakpoint.
+    fn after_step(&mut self, cpu: &Cpu) -> bool {
+        let pc = cpu.pc();
+        if let Some(tracer) = &mut self.call_tracer {
+            tracer.trace(pc, self.symbols);
+        }
+
+        let hit = self.breakpoints.contains(&pc);
+        if hit {
+            eprintln!(
+                "breakpoint hit; press F5 to continue\n{}",
+                self.dump_cpu(cpu)
+            );
+        }
+
+        hit
+    }
+}
+
+enum Boot {
+    Cartridge,
+    Multiboot(Vec<u8>),
+    Elf(Elf),
+}
+
+/// Loads the cartridge and connects any extra hardware requested. If the file is a multiboot image,
+/// an empty cartridge is returned along with the image.
+fn load_cart(matches: &ArgMatches) -> Result<(Cartridge, Boot)> {
+    let cart_file = Path::new(matches.value_of_os("FILE").unwrap());
+    let patch_file = matches.value_of_os("patch").map(Path::new);
 
-    let matches = command!()
+    let mut cart = Cartridge::from_file(cart_file, matches.value_of("entry"), patch_file)
+        .context("failed to read cartridge ROM file")?;
+
+    let mut boot = Boot::Cartridge;
+    if elf::is_elf(cart.rom()) {
+        let elf = Elf::parse(cart.rom()).context("failed to load ELF file")?;
+        cart = Cartridge::new(elf.rom_image());
+        boot = Boot::Elf(elf);
+    } else if matches.is_present("multiboot")
+        || multiboot::is_image(cart_file)
+        || multiboot::looks_like_image(cart.rom())
+    {
+        let image = cart.rom().to_vec();
+        multiboot::check_image(&image, matches.is_present("multiboot-slave"))?;
+        boot = Boot::Multiboot(image);
+
+        // Multiboot images run without a cartridge inserted.
+        cart = Cartridge::new(Vec::new());
+    }
+
+    if matches.is_present("rtc") {
+        cart.enable_rtc();
+    }
+    if let Some(time) = matches.value_of("rtc-time") {
+        let time = time
+            .parse::<DateTime>()
+            .context("invalid real-time clock time")?;
+        cart.enable_rtc()
+            .set_time_source(TimeSource::Fixed(time.to_unix_secs()));
+    }
+
+    for sensor in matches.values_of("sensor").into_iter().flatten() {
+        match sensor {
+            "solar" => {
+                cart.enable_solar_sensor();
+            }
+            "tilt" => {
+                cart.enable_tilt_sensor();
+            }
+            "gyro" => {
+                cart.enable_gyro();
+            }
+            "rumble" => {
+                cart.enable_rumble();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    Ok((cart, boot))
+}
+
+/// Applies the video options to the GBA.
+fn configure_video(gba: &mut Gba, matches: &ArgMatches, config: &Config) {
+    if matches.is_present("video-thread") {
+        gba.enable_video_thread();
+    }
+    gba.set_colour_profile(config.colour_profile);
+    gba.set_frame_blend_mode(matches.value_of("frame-blend").map(|mode| match mode {
+        "mix" => FrameBlendMode::Mix,
+        "decay" => FrameBlendMode::Decay,
+        _ => unreachable!(),
+    }));
+}
+
+/// The config file, which settings changed while running are saved to.
+struct ConfigFile {
+    path: Option<PathBuf>,
+    /// The settings in the file, which don't include those overridden by options.
+    saved: Config,
+}
+
+impl ConfigFile {
+    /// Saves the settings that changed from `old` to `new`, so they persist between runs.
+    fn save_changes(&mut self, old: &Config, new: &Config) -> Result<()> {
+        self.saved.apply_changes(old, new);
+        match &self.path {
+            Some(path) => self
+                .saved
+                .save(path)
+                .with_context(|| format!("failed to save config file {}", path.display())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Loads the config file, returning its settings overridden by those given as options. The
+/// options only apply to this run, so they aren't saved to the file.
+fn load_config(matches: &ArgMatches) -> Result<(Config, ConfigFile)> {
+    let path = matches
+        .value_of_os("config")
+        .map(PathBuf::from)
+        .or_else(Config::default_path);
+    let saved = match &path {
+        Some(path) => Config::load(path)
+            .with_context(|| format!("failed to load config file {}", path.display()))?,
+        None => Config::default(),
+    };
+    let mut config = saved;
+
+    if matches.is_present("scale") {
+        config.scale = matches
+            .value_of_t("scale")
+            .context("invalid window scale")?;
+        if config.scale == 0 {
+            return Err(anyhow!("window scale must be at least 1"));
+        }
+    }
+    if matches.is_present("fullscreen") {
+        config.fullscreen = true;
+    } else if matches.is_present("windowed") {
+        config.fullscreen = false;
+    }
+    if let Some(scaling) = matches.value_of("scaling") {
+        config.scaling = scaling.parse()?;
+    }
+    if matches.is_present("upscaler") {
+        config.upscaler = matches.value_of_t("upscaler").context("invalid upscaler")?;
+    }
+    if let Some(profile) = matches.value_of("colour-profile") {
+        config.colour_profile = profile.parse()?;
+    }
+
+    Ok((config, ConfigFile { path, saved }))
+}
+
+fn parse_args() -> ArgMatches {
+    command!()
         .arg(arg!(--bios <FILE> "BIOS ROM file to use").allow_invalid_utf8(true))
+        .arg(arg!(--rtc "Connect a real-time clock to the cartridge"))
+        .arg(arg!(--"rtc-time" [TIME] "Fixed RTC start time (YYYY-MM-DDTHH:MM:SS)"))
+        .arg(
+            arg!(--sensor [SENSOR]... "Connect a sensor to the cartridge")
+                .possible_values(["solar", "tilt", "gyro", "rumble"]),
+        )
+        .arg(
+            arg!(--"light-level" [LEVEL] "Initial solar sensor light level (0-255)")
+                .default_value("128"),
+        )
         .arg(arg!(<FILE> "Cartridge ROM file to execute").allow_invalid_utf8(true))
-        .get_matches();
+        .arg(arg!(--entry [NAME] "Name of the ROM file to load from a zip archive"))
+        .arg(
+            arg!(--patch [FILE] "IPS, UPS or BPS patch to apply to the ROM")
+                .allow_invalid_utf8(true),
+        )
+        .arg(arg!(--multiboot "Load FILE as a multiboot image, even if it isn't detected as one"))
+        .arg(
+            arg!(--"multiboot-slave" "Boot multiboot images as if received as slave 1").long_help(
+                "Check the header checksum of multiboot images, and fill in the boot mode and \
+                     slave ID that the BIOS writes after receiving one as slave 1 in multiplay \
+                     mode. The link cable transfer itself isn't emulated.",
+            ),
+        )
+        .arg(arg!(--"trace-calls" "Print the function being executed whenever it changes"))
+        .arg(arg!(--"break" [NAME]... "Pause before executing the ELF symbol NAME (F5 continues)"))
+        .arg(arg!(--"video-thread" "Compose the screen on a background thread"))
+        .arg(
+            arg!(--"colour-profile" [PROFILE] "Colour correction to apply to the screen")
+                .possible_values(["raw", "gba", "gba-sp", "gbp"]),
+        )
+        .arg(
+            arg!(--"frame-blend" [MODE] "Blend frames together like the GBA's slow LCD")
+                .possible_values(["mix", "decay"]),
+        )
+        .arg(
+            arg!(--upscaler [FILTER] "Upscaler for the screen, cycled with F2 while running")
+                .long_help(
+                    "Upscaler for the screen, cycled with F2 while running: none, nearestN \
+                     (N = 1-8), scale2x, scale3x, xbrzN (N = 2-6) or gridN (N = 2-8)",
+                ),
+        )
+        .arg(
+            arg!(--screenshot [FILE] "Save the last frame as a PNG on exit (F12 saves one now)")
+                .allow_invalid_utf8(true),
+        )
+        .arg(arg!(--scale [N] "Initial window size, as a multiple of the screen size"))
+        .arg(arg!(--fullscreen "Start in fullscreen, which is toggled with F11 or Alt+Enter"))
+        .arg(
+            arg!(--windowed "Start in a window, even if fullscreen was saved")
+                .conflicts_with("fullscreen"),
+        )
+        .arg(
+            arg!(--scaling [MODE] "How the screen is fitted to the window")
+                .possible_values(["stretch", "aspect", "integer"]),
+        )
+        .arg(
+            arg!(--config [FILE] "File to load and save display settings in")
+                .allow_invalid_utf8(true),
+        )
+        .get_matches()
+}
 
-    let bios_file = Path::new(matches.value_of_os("bios").unwrap());
-    let cart_file = Path::new(matches.value_of_os("FILE").unwrap());
+fn main() -> Result<()> {
+    const REDRAW_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    let matches = parse_args();
 
+    let bios_file = Path::new(matches.value_of_os("bios").unwrap());
     let bios = Bios::from_file(bios_file).context("failed to read BIOS ROM file")?;
-    let mut cart = Cartridge::from_file(cart_file).context("failed to read cartridge ROM file")?;
+    let (mut cart, boot) = load_cart(&matches)?;
+    let light_level = matches
+        .value_of_t("light-level")
+        .context("invalid solar sensor light level")?;
+    let (mut config, mut config_file) = load_config(&matches)?;
 
-    let mut context = SdlContext::init()?;
-    let mut screen = SdlScreen::new(&context.win_texture_creator)?;
+    let mut context = SdlContext::init(&config)?;
+    let mut screen = SdlScreen::new(&context.win_texture_creator, config.upscaler)?;
     context.win_canvas.set_draw_color(Color::BLACK);
     context.win_canvas.clear();
     context.win_canvas.present();
 
+    let mut cart_input = CartInput {
+        controller: None,
+        light_level,
+    };
+
     let mut gba = Gba::new(&bios, &mut cart);
-    gba.reset_and_skip_bios();
+    configure_video(&mut gba, &matches, &config);
+    match &boot {
+        Boot::Cartridge => gba.reset_and_skip_bios(),
+        Boot::Multiboot(image) => {
+            gba.reset_and_boot_multiboot(image, matches.is_present("multiboot-slave"));
+        }
+        Boot::Elf(elf) => gba.reset_and_boot_elf(elf),
+    }
+
+    let symbols = match &boot {
+        Boot::Elf(elf) => Some(&elf.symbols),
+        _ => None,
+    };
+    let mut debugger = Debugger::new(&matches, symbols)?;
+    let mut paused = false;
 
     let mut next_redraw_time = Instant::now() + REDRAW_DURATION;
     'main_loop: loop {
-        gba.step(&mut screen);
+        if paused {
+            thread::sleep(next_redraw_time.saturating_duration_since(Instant::now()));
+        } else {
+            // Dump the CPU state if the emulator panics, which is more useful with symbols.
+            if panic::catch_unwind(AssertUnwindSafe(|| gba.step(&mut screen))).is_err() {
+                eprintln!("{}", debugger.dump_cpu(gba.cpu()));
+                return Err(anyhow!("emulator crashed"));
+            }
+            paused = debugger.after_step(gba.cpu());
+        }
 
         let now = Instant::now();
         if now >= next_redraw_time {
@@ -163,20 +680,56 @@ fn main() -> Result<()> {
                 next_redraw_time = now + REDRAW_DURATION;
             }
 
-            for event in context.event_pump.poll_iter() {
-                if let Event::Quit { .. } = event {
-                    break 'main_loop;
+            while let Some(event) = context.event_pump.poll_event() {
+                match event {
+                    Event::Quit { .. } => break 'main_loop,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F2),
+                        ..
+                    } => cycle_upscaler(&mut context.win_canvas, &mut screen)?,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F5),
+                        ..
+                    } => paused = false,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F12),
+                        ..
+                    } => save_screenshot(&screen)?,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F11),
+                        ..
+                    } => toggle_fullscreen(&mut context.win_canvas)?,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Return),
+                        keymod,
+                        ..
+                    } if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) => {
+                        toggle_fullscreen(&mut context.win_canvas)?;
+                    }
+                    _ => {}
                 }
+                cart_input.handle_event(&context, &event);
             }
+            cart_input.update_cart(&context, gba.cart_mut());
 
-            context.win_canvas.clear();
-            context
-                .win_canvas
-                .copy(screen.get_texture()?, None, None)
-                .map_err(|e| anyhow!("failed to draw screen texture: {e}"))?;
-            context.win_canvas.present();
+            // Save settings changed while running straight away, so they're kept however we exit.
+            let current_config = Config {
+                fullscreen: is_fullscreen(&context.win_canvas),
+                upscaler: screen.upscaler,
+                ..config
+            };
+            if current_config != config {
+                config_file.save_changes(&config, &current_config)?;
+                config = current_config;
+            }
+
+            draw_screen(&mut context.win_canvas, &mut screen, config.scaling)?;
         }
     }
 
+    if let Some(path) = matches.value_of_os("screenshot") {
+        screenshot::save(Path::new(path), &screen.frame_buf, screen.upscaler)?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,103 @@
+use std::{ffi::OsStr, path::Path};
+
+use anyhow::{bail, Result};
+
+/// Multiboot images are transferred to the start of external WRAM.
+pub const LOAD_ADDR: u32 = 0x0200_0000;
+pub const MAX_LEN: usize = 0x4_0000;
+
+/// The BIOS starts multiboot images at this address, rather than at the start of the image.
+pub const ENTRY_ADDR: u32 = LOAD_ADDR + 0xc0;
+
+/// Offsets of the header bytes written by the BIOS after a successful transfer.
+pub const BOOT_MODE_OFFSET: usize = 0xc4;
+pub const SLAVE_ID_OFFSET: usize = 0xc5;
+
+fn header_checksum(image: &[u8]) -> u8 {
+    image[0xa0..0xbd]
+        .iter()
+        .fold(0u8, |acc, &b| acc.wrapping_sub(b))
+        .wrapping_sub(0x19)
+}
+
+/// Returns whether the file at `path` is a multiboot image, going by its extension.
+pub fn is_image(path: impl AsRef<Path>) -> bool {
+    let ext = path.as_ref().extension().and_then(OsStr::to_str);
+
+    matches!(ext, Some(ext) if ext.eq_ignore_ascii_case("mb"))
+}
+
+/// Returns whether `image` looks like a multiboot image rather than a cartridge ROM, going by its
+/// header: it must fit in EWRAM, have a valid header checksum, and have more pointers into EWRAM
+/// than into ROM in its literal pools, as multiboot images are linked to run from EWRAM.
+pub fn looks_like_image(image: &[u8]) -> bool {
+    if image.len() > MAX_LEN || image.len() < 0xc0 || header_checksum(image) != image[0xbd] {
+        return false;
+    }
+
+    let (mut ewram_ptrs, mut rom_ptrs) = (0, 0);
+    for word in image[0xc0..].chunks_exact(4).take(0x4000) {
+        match u32::from_le_bytes(word.try_into().unwrap()) {
+            0x0200_0000..=0x0203_ffff => ewram_ptrs += 1,
+            0x0800_0000..=0x09ff_ffff => rom_ptrs += 1,
+            _ => {}
+        }
+    }
+
+    ewram_ptrs > rom_ptrs
+}
+
+/// Checks that `image` can be loaded. If `check_header` is set, also checks its header checksum
+/// like the BIOS does.
+pub fn check_image(image: &[u8], check_header: bool) -> Result<()> {
+    if image.len() > MAX_LEN {
+        bail!(
+            "multiboot image is {} bytes, but can be at most {MAX_LEN} bytes",
+            image.len()
+        );
+    }
+    if image.len() < 0xc0 {
+        bail!("multiboot image is too small to contain a header");
+    }
+    if check_header {
+        let checksum = header_checksum(image);
+        if checksum != image[0xbd] {
+            bail!(
+                "multiboot image has header checksum {:02x}, expected {checksum:02x}",
+                image[0xbd]
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_image(ptr: u32) -> Vec<u8> {
+        let mut image = vec![0; 0x200];
+        image[0xa0..0xac].copy_from_slice(b"TEST        ");
+        image[0xbd] = header_checksum(&image);
+        image[0x100..0x104].copy_from_slice(&ptr.to_le_bytes());
+
+        image
+    }
+
+    #[test]
+    fn is_image_works() {
+        assert!(is_image("test.MB"));
+        assert!(!is_image("test.gba"));
+        assert!(looks_like_image(&make_image(0x0200_1234)));
+        assert!(!looks_like_image(&make_image(0x0800_1234)));
+
+        let mut image = make_image(0x0200_1234);
+        image[0xbd] ^= 1;
+        assert!(!looks_like_image(&image));
+        assert!(check_image(&image, false).is_ok());
+        assert!(check_image(&image, true).is_err());
+        assert!(check_image(&vec![0; MAX_LEN + 1], false).is_err());
+        assert!(check_image(&[0; 0xbf], false).is_err());
+    }
+}
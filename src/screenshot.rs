@@ -0,0 +1,131 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{
+    upscale::Upscaler,
+    video::{FrameBuffer, FRAME_HEIGHT, FRAME_WIDTH},
+};
+
+/// Returns the first path of the form "screenshotN.png" in the current directory that doesn't
+/// exist yet.
+pub fn next_path() -> PathBuf {
+    let mut n = 1;
+    loop {
+        let path = PathBuf::from(format!("screenshot{n}.png"));
+        if !path.exists() {
+            return path;
+        }
+        n += 1;
+    }
+}
+
+/// Saves the frame as a PNG image, upscaled like it is on the screen.
+pub fn save(path: &Path, frame_buf: &FrameBuffer, upscaler: Upscaler) -> Result<()> {
+    let png = encode_frame(frame_buf, upscaler)?;
+
+    fs::write(path, png).with_context(|| format!("failed to save {}", path.display()))
+}
+
+fn encode_frame(frame_buf: &FrameBuffer, upscaler: Upscaler) -> Result<Vec<u8>> {
+    let mut scaled_buf = Vec::new();
+    upscaler.upscale(&frame_buf.0, FRAME_WIDTH, FRAME_HEIGHT, &mut scaled_buf);
+    let factor = upscaler.factor();
+
+    encode_png(&scaled_buf, FRAME_WIDTH * factor, FRAME_HEIGHT * factor)
+}
+
+/// Encodes a `width` x `height` image of 24-bit colours as an RGB PNG.
+fn encode_png(dots: &[u32], width: usize, height: usize) -> Result<Vec<u8>> {
+    // Each row is preceded by its filter type, which is always 0 (none).
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in dots.chunks(width) {
+        encoder.write_all(&[0])?;
+        for colour in row {
+            encoder.write_all(&colour.to_le_bytes()[..3])?;
+        }
+    }
+    let idat = encoder.finish()?;
+
+    #[allow(clippy::cast_possible_truncation)]
+    let mut ihdr = [(width as u32).to_be_bytes(), (height as u32).to_be_bytes()].concat();
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB, not interlaced
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, data) in [(b"IHDR", &ihdr[..]), (b"IDAT", &idat), (b"IEND", &[])] {
+        #[allow(clippy::cast_possible_truncation)]
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let crc_start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        let crc = crc32fast::hash(&png[crc_start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::ZlibDecoder;
+
+    use super::*;
+
+    #[test]
+    fn encode_png_works() {
+        let png = encode_png(&[0x33_22_11, 0x66_55_44, 0xff_ff_ff, 0], 2, 2).unwrap();
+        assert_eq!(b"\x89PNG\r\n\x1a\n", &png[..8]);
+
+        // Each chunk is its length, type, data and the CRC of its type and data
+        let mut chunks = Vec::new();
+        let mut offset = 8;
+        while offset < png.len() {
+            let len = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+            let (kind, data) = (&png[offset + 4..offset + 8], &png[offset + 8..][..len]);
+            let crc = &png[offset + 8 + len..][..4];
+            assert_eq!(
+                crc32fast::hash(&png[offset + 4..offset + 8 + len]).to_be_bytes(),
+                crc
+            );
+
+            chunks.push((kind, data));
+            offset += len + 12;
+        }
+        assert_eq!(3, chunks.len());
+        assert_eq!(
+            (&b"IHDR"[..], &[0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0][..]),
+            chunks[0]
+        );
+        assert_eq!((&b"IEND"[..], &[][..]), chunks[2]);
+
+        let mut rows = Vec::new();
+        assert_eq!(b"IDAT", chunks[1].0);
+        ZlibDecoder::new(chunks[1].1)
+            .read_to_end(&mut rows)
+            .unwrap();
+        #[rustfmt::skip]
+        assert_eq!(
+            [
+                0, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66,
+                0, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00,
+            ],
+            &rows[..]
+        );
+    }
+
+    #[test]
+    fn encode_frame_works() {
+        let png = encode_frame(&FrameBuffer::default(), Upscaler::Nearest(2)).unwrap();
+
+        // The IHDR chunk holds the upscaled size
+        assert_eq!(2 * 240, u32::from_be_bytes(png[16..20].try_into().unwrap()));
+        assert_eq!(2 * 160, u32::from_be_bytes(png[20..24].try_into().unwrap()));
+    }
+}
@@ -0,0 +1,209 @@
+mod scalex;
+mod xbrz;
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Context};
+
+/// An image being upscaled. Reads outside of it are clamped to its edges.
+struct Source<'a> {
+    dots: &'a [u32],
+    width: usize,
+    height: usize,
+}
+
+impl Source<'_> {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss
+    )]
+    fn get(&self, x: isize, y: isize) -> u32 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+
+        self.dots[y * self.width + x]
+    }
+
+    /// Returns the 3x3 block of dots centred on (`x`, `y`), in row-major order.
+    fn kernel3x3(&self, x: isize, y: isize) -> [u32; 9] {
+        let mut kernel = [0; 9];
+        for (i, dot) in kernel.iter_mut().enumerate() {
+            #[allow(clippy::cast_possible_wrap)]
+            let (dx, dy) = ((i % 3) as isize - 1, (i / 3) as isize - 1);
+            *dot = self.get(x + dx, y + dy);
+        }
+
+        kernel
+    }
+}
+
+/// A filter for upscaling frames, which runs on the CPU.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Upscaler {
+    /// Repeats each dot by the given factor.
+    Nearest(usize),
+    /// The Scale2x or Scale3x pixel art scaler, given the factor.
+    ScaleNx(usize),
+    /// The xBRZ pixel art scaler, given a factor from 2 to 6.
+    Xbrz(usize),
+    /// Repeats each dot by the given factor, darkening its edges like the grid of an LCD.
+    Grid(usize),
+}
+
+impl Upscaler {
+    /// The upscalers that can be cycled through at runtime.
+    pub const PRESETS: [Self; 7] = [
+        Self::Nearest(1),
+        Self::Nearest(3),
+        Self::ScaleNx(2),
+        Self::ScaleNx(3),
+        Self::Xbrz(3),
+        Self::Xbrz(6),
+        Self::Grid(4),
+    ];
+
+    pub fn factor(self) -> usize {
+        match self {
+            Self::Nearest(factor)
+            | Self::ScaleNx(factor)
+            | Self::Xbrz(factor)
+            | Self::Grid(factor) => factor,
+        }
+    }
+
+    /// Upscales the `width` x `height` image `src`, replacing the contents of `dst` with the
+    /// result.
+    pub fn upscale(self, src: &[u32], width: usize, height: usize, dst: &mut Vec<u32>) {
+        let factor = self.factor();
+        dst.clear();
+        dst.resize(src.len() * factor * factor, 0);
+
+        let src = Source {
+            dots: src,
+            width,
+            height,
+        };
+        match self {
+            Self::Nearest(_) => nearest(&src, factor, dst),
+            Self::ScaleNx(2) => scalex::scale2x(&src, dst),
+            Self::ScaleNx(_) => scalex::scale3x(&src, dst),
+            Self::Xbrz(_) => xbrz::scale(&src, factor, dst),
+            Self::Grid(_) => grid(&src, factor, dst),
+        }
+    }
+}
+
+impl FromStr for Upscaler {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => return Ok(Self::Nearest(1)),
+            "scale2x" => return Ok(Self::ScaleNx(2)),
+            "scale3x" => return Ok(Self::ScaleNx(3)),
+            _ => {}
+        }
+
+        let digits_idx = s
+            .find(|c: char| c.is_ascii_digit())
+            .ok_or_else(|| anyhow!("missing scale factor"))?;
+        let (name, factor) = s.split_at(digits_idx);
+        let factor = factor.parse().context("invalid scale factor")?;
+
+        match (name, factor) {
+            ("nearest", 1..=8) => Ok(Self::Nearest(factor)),
+            ("xbrz", 2..=6) => Ok(Self::Xbrz(factor)),
+            ("grid", 2..=8) => Ok(Self::Grid(factor)),
+            ("nearest" | "xbrz" | "grid", _) => Err(anyhow!("unsupported scale factor")),
+            _ => Err(anyhow!("unknown upscaler")),
+        }
+    }
+}
+
+impl fmt::Display for Upscaler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Nearest(1) => write!(f, "none"),
+            Self::Nearest(factor) => write!(f, "nearest{factor}"),
+            Self::ScaleNx(factor) => write!(f, "scale{factor}x"),
+            Self::Xbrz(factor) => write!(f, "xbrz{factor}"),
+            Self::Grid(factor) => write!(f, "grid{factor}"),
+        }
+    }
+}
+
+fn nearest(src: &Source, factor: usize, dst: &mut [u32]) {
+    let dst_width = src.width * factor;
+    for (i, &colour) in src.dots.iter().enumerate() {
+        let (x, y) = (i % src.width, i / src.width);
+        for dy in 0..factor {
+            let offset = (y * factor + dy) * dst_width + x * factor;
+            dst[offset..offset + factor].fill(colour);
+        }
+    }
+}
+
+fn grid(src: &Source, factor: usize, dst: &mut [u32]) {
+    nearest(src, factor, dst);
+
+    // Darken the right and bottom edges of each dot.
+    let dst_width = src.width * factor;
+    for (i, colour) in dst.iter_mut().enumerate() {
+        let (x, y) = (i % dst_width, i / dst_width);
+        if x % factor == factor - 1 || y % factor == factor - 1 {
+            #[allow(clippy::cast_possible_truncation)]
+            let channels = colour.to_le_bytes().map(|c| (u16::from(c) * 3 / 4) as u8);
+            *colour = u32::from_le_bytes(channels);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_works() {
+        assert_eq!(Upscaler::Nearest(1), "none".parse().unwrap());
+        assert_eq!(Upscaler::Nearest(4), "nearest4".parse().unwrap());
+        assert_eq!(Upscaler::ScaleNx(3), "scale3x".parse().unwrap());
+        assert_eq!(Upscaler::Xbrz(6), "xbrz6".parse().unwrap());
+        assert_eq!(Upscaler::Grid(2), "grid2".parse().unwrap());
+        assert!("xbrz7".parse::<Upscaler>().is_err());
+        assert!("grid".parse::<Upscaler>().is_err());
+        assert!("bilinear2".parse::<Upscaler>().is_err());
+
+        for upscaler in Upscaler::PRESETS {
+            assert_eq!(upscaler, upscaler.to_string().parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn nearest_and_grid_work() {
+        let mut dst = Vec::new();
+        Upscaler::Nearest(2).upscale(&[1, 2, 3, 4], 2, 2, &mut dst);
+        #[rustfmt::skip]
+        assert_eq!(
+            [
+                1, 1, 2, 2,
+                1, 1, 2, 2,
+                3, 3, 4, 4,
+                3, 3, 4, 4,
+            ],
+            &dst[..]
+        );
+
+        Upscaler::Grid(3).upscale(&[0x80_40_ff], 1, 1, &mut dst);
+        let edge = 0x60_30_bf;
+        #[rustfmt::skip]
+        assert_eq!(
+            [
+                0x80_40_ff, 0x80_40_ff, edge,
+                0x80_40_ff, 0x80_40_ff, edge,
+                edge,       edge,       edge,
+            ],
+            &dst[..]
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! The Scale2x and Scale3x pixel art scalers (also known as `AdvMAME2x` and `AdvMAME3x`), which extend
+//! edges between dots of the same colour diagonally.
+
+use super::Source;
+
+#[allow(clippy::cast_possible_wrap, clippy::many_single_char_names)]
+pub fn scale2x(src: &Source, dst: &mut [u32]) {
+    let dst_width = 2 * src.width;
+    for y in 0..src.height {
+        for x in 0..src.width {
+            //   A
+            // C P B
+            //   D
+            let [_, a, _, c, p, b, _, d, _] = src.kernel3x3(x as isize, y as isize);
+            let out = [
+                if c == a && c != d && a != b { a } else { p },
+                if a == b && a != c && b != d { b } else { p },
+                if d == c && d != b && c != a { c } else { p },
+                if b == d && b != a && d != c { d } else { p },
+            ];
+
+            let offset = 2 * y * dst_width + 2 * x;
+            dst[offset..offset + 2].copy_from_slice(&out[..2]);
+            dst[offset + dst_width..offset + dst_width + 2].copy_from_slice(&out[2..]);
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_wrap, clippy::many_single_char_names)]
+pub fn scale3x(src: &Source, dst: &mut [u32]) {
+    let dst_width = 3 * src.width;
+    for y in 0..src.height {
+        for x in 0..src.width {
+            // A B C
+            // D E F
+            // G H I
+            let [a, b, c, d, e, f, g, h, i] = src.kernel3x3(x as isize, y as isize);
+            let (db, bf, dh, hf) = (
+                d == b && d != h && b != f,
+                b == f && b != d && f != h,
+                d == h && d != b && h != f,
+                h == f && h != d && f != b,
+            );
+            let out = [
+                if db { d } else { e },
+                if (db && e != c) || (bf && e != a) {
+                    b
+                } else {
+                    e
+                },
+                if bf { f } else { e },
+                if (db && e != g) || (dh && e != a) {
+                    d
+                } else {
+                    e
+                },
+                e,
+                if (bf && e != i) || (hf && e != c) {
+                    f
+                } else {
+                    e
+                },
+                if dh { d } else { e },
+                if (dh && e != i) || (hf && e != g) {
+                    h
+                } else {
+                    e
+                },
+                if hf { f } else { e },
+            ];
+
+            for (row, dots) in out.chunks(3).enumerate() {
+                let offset = (3 * y + row) * dst_width + 3 * x;
+                dst[offset..offset + 3].copy_from_slice(dots);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Upscaler;
+
+    // A diagonal line of B on a background of W.
+    const W: u32 = 0xff_ff_ff;
+    const B: u32 = 0x00_00_00;
+    #[rustfmt::skip]
+    const DIAGONAL: [u32; 9] = [
+        W, W, B,
+        W, B, W,
+        B, W, W,
+    ];
+
+    #[test]
+    fn scale2x_works() {
+        let mut dst = Vec::new();
+        Upscaler::ScaleNx(2).upscale(&DIAGONAL, 3, 3, &mut dst);
+        #[rustfmt::skip]
+        assert_eq!(
+            [
+                W, W, W, W, B, B,
+                W, W, W, B, W, B,
+                W, W, B, B, B, W,
+                W, B, B, B, W, W,
+                B, W, B, W, W, W,
+                B, B, W, W, W, W,
+            ],
+            &dst[..]
+        );
+    }
+
+    #[test]
+    fn scale3x_works() {
+        let mut dst = Vec::new();
+        Upscaler::ScaleNx(3).upscale(&DIAGONAL, 3, 3, &mut dst);
+
+        // The line is thickened by filling in the corners of the dots next to it
+        let block =
+            |x: usize, y: usize| [0, 1, 2].map(|row| &dst[(3 * y + row) * 9 + 3 * x..][..3]);
+        assert_eq!([[B, B, B], [B, B, B], [B, B, B]], block(1, 1));
+        assert_eq!([[W, W, W], [W, W, B], [W, W, B]], block(1, 0));
+        assert_eq!([[W, W, W], [W, W, W], [W, B, B]], block(0, 1));
+
+        // Solid colours are unchanged
+        Upscaler::ScaleNx(3).upscale(&[W; 4], 2, 2, &mut dst);
+        assert!(dst.iter().all(|&dot| dot == W));
+    }
+}
@@ -0,0 +1,427 @@
+//! The xBRZ pixel art scaler, which detects edges between dots and blends them into smooth lines
+//! and rounded corners.
+
+use super::Source;
+
+#[derive(Copy, Clone, Eq, PartialEq, PartialOrd, Ord, Debug)]
+enum BlendType {
+    None,
+    Normal,
+    /// A strongly dominant edge, which is always blended as a line.
+    Dominant,
+}
+
+/// The blend types of the corners of a dot, clockwise from the top-left.
+type BlendInfo = [BlendType; 4];
+
+const TOP_RIGHT: usize = 1;
+const BOTTOM_RIGHT: usize = 2;
+const BOTTOM_LEFT: usize = 3;
+
+const LUMINANCE_WEIGHT: f64 = 1.0;
+const EQUAL_COLOUR_TOLERANCE: f64 = 30.0;
+const DOMINANT_DIRECTION_THRESHOLD: f64 = 3.6;
+const STEEP_DIRECTION_THRESHOLD: f64 = 2.2;
+
+/// Blends the dot at (row, column) of the output block towards the blend colour by the weight
+/// numerator / denominator.
+type Op = (usize, usize, u32, u32);
+
+/// How each shape is blended into the bottom-right corner of an output block, for a scale factor.
+/// The steep line is blended like the shallow one, transposed.
+struct Scaler {
+    shallow: &'static [Op],
+    steep_and_shallow: &'static [Op],
+    diagonal: &'static [Op],
+    corner: &'static [Op],
+}
+
+const SCALERS: [Scaler; 5] = [
+    Scaler {
+        shallow: &[(1, 0, 1, 4), (1, 1, 3, 4)],
+        steep_and_shallow: &[(1, 0, 1, 4), (0, 1, 1, 4), (1, 1, 5, 6)],
+        diagonal: &[(1, 1, 1, 2)],
+        corner: &[(1, 1, 21, 100)],
+    },
+    Scaler {
+        shallow: &[(2, 0, 1, 4), (1, 2, 1, 4), (2, 1, 3, 4), (2, 2, 1, 1)],
+        steep_and_shallow: &[
+            (2, 0, 1, 4),
+            (0, 2, 1, 4),
+            (2, 1, 3, 4),
+            (1, 2, 3, 4),
+            (2, 2, 1, 1),
+        ],
+        diagonal: &[(1, 2, 1, 8), (2, 1, 1, 8), (2, 2, 7, 8)],
+        corner: &[(2, 2, 45, 100)],
+    },
+    Scaler {
+        shallow: &[
+            (3, 0, 1, 4),
+            (2, 2, 1, 4),
+            (3, 1, 3, 4),
+            (2, 3, 3, 4),
+            (3, 2, 1, 1),
+            (3, 3, 1, 1),
+        ],
+        steep_and_shallow: &[
+            (3, 1, 3, 4),
+            (1, 3, 3, 4),
+            (3, 0, 1, 4),
+            (0, 3, 1, 4),
+            (2, 2, 1, 3),
+            (3, 3, 1, 1),
+            (3, 2, 1, 1),
+            (2, 3, 1, 1),
+        ],
+        diagonal: &[(3, 2, 1, 2), (2, 3, 1, 2), (3, 3, 1, 1)],
+        corner: &[(3, 3, 68, 100), (3, 2, 9, 100), (2, 3, 9, 100)],
+    },
+    Scaler {
+        shallow: &[
+            (4, 0, 1, 4),
+            (3, 2, 1, 4),
+            (2, 4, 1, 4),
+            (4, 1, 3, 4),
+            (3, 3, 3, 4),
+            (4, 2, 1, 1),
+            (4, 3, 1, 1),
+            (4, 4, 1, 1),
+            (3, 4, 1, 1),
+        ],
+        steep_and_shallow: &[
+            (0, 4, 1, 4),
+            (2, 3, 1, 4),
+            (1, 4, 3, 4),
+            (4, 0, 1, 4),
+            (3, 2, 1, 4),
+            (4, 1, 3, 4),
+            (3, 3, 2, 3),
+            (2, 4, 1, 1),
+            (3, 4, 1, 1),
+            (4, 4, 1, 1),
+            (4, 2, 1, 1),
+            (4, 3, 1, 1),
+        ],
+        diagonal: &[
+            (4, 2, 1, 8),
+            (3, 3, 1, 8),
+            (2, 4, 1, 8),
+            (4, 3, 7, 8),
+            (3, 4, 7, 8),
+            (4, 4, 1, 1),
+        ],
+        corner: &[(4, 4, 86, 100), (4, 3, 23, 100), (3, 4, 23, 100)],
+    },
+    Scaler {
+        shallow: &[
+            (5, 0, 1, 4),
+            (4, 2, 1, 4),
+            (3, 4, 1, 4),
+            (5, 1, 3, 4),
+            (4, 3, 3, 4),
+            (3, 5, 3, 4),
+            (5, 2, 1, 1),
+            (5, 3, 1, 1),
+            (5, 4, 1, 1),
+            (5, 5, 1, 1),
+            (4, 4, 1, 1),
+            (4, 5, 1, 1),
+        ],
+        steep_and_shallow: &[
+            (0, 5, 1, 4),
+            (2, 4, 1, 4),
+            (1, 5, 3, 4),
+            (3, 4, 3, 4),
+            (5, 0, 1, 4),
+            (4, 2, 1, 4),
+            (5, 1, 3, 4),
+            (4, 3, 3, 4),
+            (2, 5, 1, 1),
+            (3, 5, 1, 1),
+            (4, 5, 1, 1),
+            (5, 5, 1, 1),
+            (4, 4, 1, 1),
+            (5, 4, 1, 1),
+            (5, 2, 1, 1),
+            (5, 3, 1, 1),
+        ],
+        diagonal: &[
+            (5, 3, 1, 2),
+            (4, 4, 1, 2),
+            (3, 5, 1, 2),
+            (4, 5, 1, 1),
+            (5, 5, 1, 1),
+            (5, 4, 1, 1),
+        ],
+        corner: &[
+            (5, 5, 97, 100),
+            (4, 5, 42, 100),
+            (5, 4, 42, 100),
+            (5, 3, 6, 100),
+            (3, 5, 6, 100),
+        ],
+    },
+];
+
+/// The distance between two 24-bit colours in YCbCr space.
+#[allow(clippy::many_single_char_names)]
+fn dist(a: u32, b: u32) -> f64 {
+    let (a, b) = (a.to_le_bytes(), b.to_le_bytes());
+    let [r, g, b] = [0, 1, 2].map(|i| f64::from(a[i]) - f64::from(b[i]));
+
+    // ITU-R BT.2020 conversion
+    let (k_b, k_r) = (0.0593, 0.2627);
+    let k_g = 1.0 - k_b - k_r;
+    let y = k_r * r + k_g * g + k_b * b;
+    let c_b = 0.5 / (1.0 - k_b) * (b - y);
+    let c_r = 0.5 / (1.0 - k_r) * (r - y);
+
+    ((LUMINANCE_WEIGHT * y).powi(2) + c_b.powi(2) + c_r.powi(2)).sqrt()
+}
+
+fn eq(a: u32, b: u32) -> bool {
+    dist(a, b) < EQUAL_COLOUR_TOLERANCE
+}
+
+/// Detects the blend types of the corner in the middle of a 4x4 block of dots, returning them for
+/// the dots around it (F, G, J and K).
+#[allow(clippy::many_single_char_names)]
+fn corner_blend(kernel: [u32; 16]) -> [BlendType; 4] {
+    // A B C D
+    // E F G H
+    // I J K L
+    // M N O P
+    let [_, b, c, _, e, f, g, h, i, j, k, l, _, n, o, _] = kernel;
+    let mut result = [BlendType::None; 4];
+    if (f == g && j == k) || (f == j && g == k) {
+        return result;
+    }
+
+    let jg = dist(i, f) + dist(f, c) + dist(n, k) + dist(k, h) + 4.0 * dist(j, g);
+    let fk = dist(e, j) + dist(j, o) + dist(b, g) + dist(g, l) + 4.0 * dist(f, k);
+    let blend_type = |dominant| {
+        if dominant {
+            BlendType::Dominant
+        } else {
+            BlendType::Normal
+        }
+    };
+    if jg < fk {
+        let blend = blend_type(DOMINANT_DIRECTION_THRESHOLD * jg < fk);
+        if f != g && f != j {
+            result[0] = blend;
+        }
+        if k != j && k != g {
+            result[3] = blend;
+        }
+    } else if fk < jg {
+        let blend = blend_type(DOMINANT_DIRECTION_THRESHOLD * fk < jg);
+        if j != f && j != k {
+            result[2] = blend;
+        }
+        if g != f && g != k {
+            result[1] = blend;
+        }
+    }
+
+    result
+}
+
+/// Blends the bottom-right corner of a dot's output block, given its 3x3 neighbourhood and blend
+/// info, both rotated so that the corner being blended is at the bottom-right.
+#[allow(clippy::many_single_char_names)]
+fn blend_corner(
+    scaler: &Scaler,
+    kernel: [u32; 9],
+    info: BlendInfo,
+    mut blend: impl FnMut(usize, usize, u32, (u32, u32)),
+) {
+    // A B C
+    // D E F
+    // G H I
+    let [_, b, c, d, e, f, g, h, i] = kernel;
+    if info[BOTTOM_RIGHT] < BlendType::Normal {
+        return;
+    }
+
+    let do_line_blend = if info[BOTTOM_RIGHT] >= BlendType::Dominant {
+        true
+    } else if (info[TOP_RIGHT] != BlendType::None && !eq(e, g))
+        || (info[BOTTOM_LEFT] != BlendType::None && !eq(e, c))
+    {
+        // Don't blend a dot that's already blended by an adjacent corner, unless it's a 90° corner
+        false
+    } else {
+        // Only round the corner of L-shapes
+        !(!eq(e, i) && eq(g, h) && eq(h, i) && eq(i, f) && eq(f, c))
+    };
+
+    // Blend towards the most similar neighbour
+    let colour = if dist(e, f) <= dist(e, h) { f } else { h };
+
+    let (ops, transpose) = if do_line_blend {
+        let (fg, hc) = (dist(f, g), dist(h, c));
+        let shallow = STEEP_DIRECTION_THRESHOLD * fg <= hc && e != g && d != g;
+        let steep = STEEP_DIRECTION_THRESHOLD * hc <= fg && e != c && b != c;
+        match (shallow, steep) {
+            (true, true) => (scaler.steep_and_shallow, false),
+            (true, false) => (scaler.shallow, false),
+            (false, true) => (scaler.shallow, true),
+            (false, false) => (scaler.diagonal, false),
+        }
+    } else {
+        (scaler.corner, false)
+    };
+
+    for &(row, col, num, den) in ops {
+        let (row, col) = if transpose { (col, row) } else { (row, col) };
+        blend(row, col, colour, (num, den));
+    }
+}
+
+/// Mixes each channel of `front` into `back` by the weight numerator / denominator.
+#[allow(clippy::cast_possible_truncation)]
+fn gradient(front: u32, back: u32, (num, den): (u32, u32)) -> u32 {
+    let (front, back) = (front.to_le_bytes(), back.to_le_bytes());
+
+    u32::from_le_bytes(
+        [0, 1, 2, 3]
+            .map(|i| ((u32::from(front[i]) * num + u32::from(back[i]) * (den - num)) / den) as u8),
+    )
+}
+
+/// Rotates a 3x3 kernel 90° clockwise.
+fn rotate_kernel(kernel: [u32; 9]) -> [u32; 9] {
+    let mut rotated = [0; 9];
+    for (i, dot) in rotated.iter_mut().enumerate() {
+        let (x, y) = (i % 3, i / 3);
+        *dot = kernel[(2 - x) * 3 + y];
+    }
+
+    rotated
+}
+
+#[allow(clippy::cast_possible_wrap)]
+pub fn scale(src: &Source, factor: usize, dst: &mut [u32]) {
+    let scaler = &SCALERS[factor - 2];
+    let (width, height) = (src.width, src.height);
+
+    // Detect how each corner between four dots is blended, including those on the edges
+    let mut blend_info = vec![[BlendType::None; 4]; width * height];
+    for y in -1..height as isize {
+        for x in -1..width as isize {
+            let mut kernel = [0; 16];
+            for (i, dot) in kernel.iter_mut().enumerate() {
+                let (dx, dy) = ((i % 4) as isize - 1, (i / 4) as isize - 1);
+                *dot = src.get(x + dx, y + dy);
+            }
+
+            let corners = corner_blend(kernel);
+            for (corner, (dx, dy, info_corner)) in corners.into_iter().zip([
+                (0, 0, BOTTOM_RIGHT),
+                (1, 0, BOTTOM_LEFT),
+                (0, 1, TOP_RIGHT),
+                (1, 1, 0),
+            ]) {
+                let (x, y) = (x + dx, y + dy);
+                if (0..width as isize).contains(&x) && (0..height as isize).contains(&y) {
+                    #[allow(clippy::cast_sign_loss)]
+                    let idx = y as usize * width + x as usize;
+                    blend_info[idx][info_corner] = corner;
+                }
+            }
+        }
+    }
+
+    let dst_width = width * factor;
+    for y in 0..height {
+        for x in 0..width {
+            let mut kernel = src.kernel3x3(x as isize, y as isize);
+            let block = y * factor * dst_width + x * factor;
+            for row in 0..factor {
+                dst[block + row * dst_width..][..factor].fill(kernel[4]);
+            }
+
+            let mut info = blend_info[y * width + x];
+            if info.iter().all(|&blend| blend == BlendType::None) {
+                continue;
+            }
+
+            // Blend each corner in turn, rotating it to the bottom-right
+            for rotation in 0..4 {
+                blend_corner(scaler, kernel, info, |row, col, colour, weight| {
+                    let (mut row, mut col) = (row, col);
+                    for _ in 0..rotation {
+                        (row, col) = (factor - 1 - col, row);
+                    }
+
+                    let dot = &mut dst[block + row * dst_width + col];
+                    *dot = gradient(colour, *dot, weight);
+                });
+                kernel = rotate_kernel(kernel);
+                info.rotate_right(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::Upscaler, *};
+
+    const W: u32 = 0xff_ff_ff;
+    const B: u32 = 0x00_00_00;
+
+    #[test]
+    fn rotate_kernel_works() {
+        let kernel = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        assert_eq!([7, 4, 1, 8, 5, 2, 9, 6, 3], rotate_kernel(kernel));
+        let mut rotated = kernel;
+        for _ in 0..4 {
+            rotated = rotate_kernel(rotated);
+        }
+        assert_eq!(kernel, rotated);
+    }
+
+    #[test]
+    fn xbrz_works() {
+        let mut dst = Vec::new();
+        for factor in 2..=6 {
+            // Solid colours are unchanged
+            Upscaler::Xbrz(factor).upscale(&[W; 12], 4, 3, &mut dst);
+            assert_eq!(12 * factor * factor, dst.len());
+            assert!(dst.iter().all(|&dot| dot == W));
+
+            // A diagonal line is smoothed, but the middle of each dot is unchanged
+            #[rustfmt::skip]
+            let diagonal = [
+                W, W, W, B,
+                W, W, B, W,
+                W, B, W, W,
+                B, W, W, W,
+            ];
+            Upscaler::Xbrz(factor).upscale(&diagonal, 4, 4, &mut dst);
+            let dst_width = 4 * factor;
+            for (i, &colour) in diagonal.iter().enumerate() {
+                let (x, y) = (i % 4 * factor, i / 4 * factor);
+                if factor % 2 == 1 {
+                    assert_eq!(colour, dst[(y + factor / 2) * dst_width + x + factor / 2]);
+                }
+            }
+            assert!(dst.iter().any(|&dot| dot != W && dot != B), "{factor}");
+
+            // Blending the line doesn't depend on which way it's rotated
+            let mirror = |image: &[u32], width| -> Vec<u32> {
+                image
+                    .chunks(width)
+                    .flat_map(|row| row.iter().rev())
+                    .copied()
+                    .collect()
+            };
+            let expected = mirror(&dst, dst_width);
+            Upscaler::Xbrz(factor).upscale(&mirror(&diagonal, 4), 4, 4, &mut dst);
+            assert_eq!(expected, dst, "{factor}");
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use super::FrameBuffer;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FrameBlendMode {
+    /// Each frame is mixed 50/50 with the previous one.
+    Mix,
+    /// The screen fades towards each new frame, like the slow response of the GBA's LCD.
+    Decay,
+}
+
+/// How much of the previous output is kept by each frame in `FrameBlendMode::Decay`, in 16ths.
+const DECAY_PERSISTENCE: u32 = 6;
+
+/// Mixes each channel of two 24-bit colours, weighting `a` by `weight` / 16 and `b` by the rest.
+#[allow(clippy::cast_possible_truncation)]
+fn mix(a: u32, b: u32, weight: u32) -> u32 {
+    let (a, b) = (a.to_le_bytes(), b.to_le_bytes());
+
+    u32::from_le_bytes(
+        [0, 1, 2, 3]
+            .map(|i| ((u32::from(a[i]) * weight + u32::from(b[i]) * (16 - weight) + 8) / 16) as u8),
+    )
+}
+
+/// Blends consecutive frames together before they are presented to the screen, which some games
+/// rely on to make OBJs flickering every other frame look transparent.
+#[derive(Debug)]
+pub(super) struct FrameBlender {
+    mode: FrameBlendMode,
+    /// The last frame drawn, before blending.
+    prev: FrameBuffer,
+    output: FrameBuffer,
+}
+
+impl FrameBlender {
+    pub fn new(mode: FrameBlendMode) -> Self {
+        Self {
+            mode,
+            prev: FrameBuffer::default(),
+            output: FrameBuffer::default(),
+        }
+    }
+
+    /// Blends `frame` with the previous frames, returning the frame to present.
+    pub fn blend(&mut self, frame: &FrameBuffer) -> &FrameBuffer {
+        let colours = self.output.0.iter_mut().zip(&*self.prev.0).zip(&*frame.0);
+        match self.mode {
+            FrameBlendMode::Mix => {
+                for ((out, &prev), &colour) in colours {
+                    *out = mix(prev, colour, 8);
+                }
+            }
+            FrameBlendMode::Decay => {
+                for ((out, _), &colour) in colours {
+                    *out = mix(*out, colour, DECAY_PERSISTENCE);
+                }
+            }
+        }
+        self.prev.0.copy_from_slice(&frame.0);
+
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mix_works() {
+        assert_eq!(0x80_40_10, mix(0xff_80_20, 0x00_00_00, 8));
+        assert_eq!(0x12_34_56, mix(0x12_34_56, 0x12_34_56, 5));
+        assert_eq!(0x00_00_ef, mix(0x00_00_ff, 0x00_00_00, 15));
+    }
+
+    #[test]
+    fn frame_blender_works() {
+        let frame = |colour| FrameBuffer(vec![colour; FrameBuffer::default().0.len()].into());
+        let (black, white) = (frame(0x00_00_00), frame(0xff_ff_ff));
+
+        // Flickering between black and white looks grey
+        let mut blender = FrameBlender::new(FrameBlendMode::Mix);
+        blender.blend(&white);
+        assert_eq!(0x80_80_80, blender.blend(&black).0[0]);
+        assert_eq!(0x80_80_80, blender.blend(&white).0[0]);
+        assert_eq!(0xff_ff_ff, blender.blend(&white).0[0]);
+
+        // Fades towards each frame, then settles on it
+        let mut blender = FrameBlender::new(FrameBlendMode::Decay);
+        assert_eq!(0x9f_9f_9f, blender.blend(&white).0[0]);
+        assert_eq!(0xdb_db_db, blender.blend(&white).0[0]);
+        for _ in 0..10 {
+            blender.blend(&white);
+        }
+        assert_eq!(0xff_ff_ff, blender.blend(&white).0[0]);
+        assert_eq!(0x60_60_60, blender.blend(&black).0[0]);
+    }
+}
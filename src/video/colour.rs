@@ -0,0 +1,152 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+
+use super::rgb555_channels;
+
+/// Expands an RGB555 colour to 24 bits, so that the full range of each channel is used.
+#[allow(clippy::cast_possible_truncation)]
+pub fn rgb555_to_24(value: u16) -> u32 {
+    let [r, g, b] = rgb555_channels(value).map(|c| ((c << 3) | (c >> 2)) as u8);
+
+    u32::from_le_bytes([r, g, b, 0])
+}
+
+/// How RGB555 colours are converted to the 24-bit colours of the frame buffer.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ColourProfile {
+    /// Colours are expanded as-is.
+    Raw,
+    /// Approximates the dark, low contrast screen of the original GBA.
+    GbaLcd,
+    /// Approximates the backlit screen of later GBA SP models.
+    GbaSp,
+    /// Approximates the output of the Game Boy Player to a TV.
+    GameBoyPlayer,
+}
+
+impl FromStr for ColourProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "gba" => Ok(Self::GbaLcd),
+            "gba-sp" => Ok(Self::GbaSp),
+            "gbp" => Ok(Self::GameBoyPlayer),
+            _ => Err(anyhow!("unknown colour profile \"{s}\"")),
+        }
+    }
+}
+
+impl fmt::Display for ColourProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Raw => "raw",
+            Self::GbaLcd => "gba",
+            Self::GbaSp => "gba-sp",
+            Self::GameBoyPlayer => "gbp",
+        })
+    }
+}
+
+/// The colour response of a display, which the colour profiles correct for.
+struct Display {
+    gamma: f64,
+    /// The contributions of the linear red, green and blue input channels to each output channel.
+    matrix: [[f64; 3]; 3],
+}
+
+const GBA_LCD: Display = Display {
+    gamma: 4.0,
+    matrix: [
+        [255.0 / 280.0, 50.0 / 280.0, 0.0],
+        [10.0 / 280.0, 230.0 / 280.0, 30.0 / 280.0],
+        [50.0 / 280.0, 10.0 / 280.0, 220.0 / 280.0],
+    ],
+};
+
+const GBA_SP: Display = Display {
+    gamma: 2.2,
+    matrix: [
+        [0.96, 0.11, -0.07],
+        [0.08, 0.83, 0.09],
+        [-0.035, 0.19, 0.845],
+    ],
+};
+
+const GAME_BOY_PLAYER: Display = Display {
+    gamma: 2.2,
+    matrix: [[0.9, 0.1, 0.0], [0.05, 0.9, 0.05], [0.0, 0.1, 0.9]],
+};
+
+const OUTPUT_GAMMA: f64 = 2.2;
+
+impl ColourProfile {
+    /// Returns a lookup table of the 24-bit colours of every RGB555 colour.
+    pub fn table(self) -> Box<[u32]> {
+        (0..0x8000).map(|colour| self.convert(colour)).collect()
+    }
+
+    fn convert(self, colour: u16) -> u32 {
+        let display = match self {
+            Self::Raw => return rgb555_to_24(colour),
+            Self::GbaLcd => GBA_LCD,
+            Self::GbaSp => GBA_SP,
+            Self::GameBoyPlayer => GAME_BOY_PLAYER,
+        };
+
+        let linear = rgb555_channels(colour).map(|c| (f64::from(c) / 31.0).powf(display.gamma));
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let [r, g, b] = display.matrix.map(|weights| {
+            let c = (0..3).map(|i| weights[i] * linear[i]).sum::<f64>();
+
+            (c.clamp(0.0, 1.0).powf(OUTPUT_GAMMA.recip()) * 255.0).round() as u8
+        });
+
+        u32::from_le_bytes([r, g, b, 0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb555_to_24_works() {
+        assert_eq!(0x00_00_00, rgb555_to_24(0));
+        assert_eq!(0xff_ff_ff, rgb555_to_24(0x7fff));
+        assert_eq!(0xff_ff_ff, rgb555_to_24(0xffff));
+        assert_eq!(0x00_00_ff, rgb555_to_24(0x001f));
+        assert_eq!(0x84_84_08, rgb555_to_24(0x4201)); // Channels 1, 16 and 16
+    }
+
+    #[test]
+    fn colour_profiles_work() {
+        let grey = 0x3def; // Channels 15, 15 and 15
+        for profile in [
+            ColourProfile::Raw,
+            ColourProfile::GbaLcd,
+            ColourProfile::GbaSp,
+            ColourProfile::GameBoyPlayer,
+        ] {
+            assert_eq!(profile, profile.to_string().parse().unwrap());
+
+            let table = profile.table();
+            assert_eq!(0x8000, table.len());
+            assert_eq!(0, table[0]);
+            assert_eq!(profile.convert(grey), table[grey as usize]);
+
+            // White stays (close to) white
+            let [r, g, b, _] = table[0x7fff].to_le_bytes();
+            assert!(r >= 0xf0 && g >= 0xf0 && b >= 0xf0, "{profile:?}");
+        }
+
+        // The original GBA's screen is much darker
+        let [r, ..] = ColourProfile::GbaLcd.convert(grey).to_le_bytes();
+        assert!(r < 0x50);
+        assert_eq!(0x7b_7b_7b, ColourProfile::Raw.convert(grey));
+
+        assert!("vivid".parse::<ColourProfile>().is_err());
+    }
+}
@@ -1,16 +1,33 @@
+mod blend;
+mod colour;
+mod obj;
 mod reg;
+mod thread;
 
-use std::ops::{Index, IndexMut};
+use std::{
+    iter,
+    ops::{Index, IndexMut, Range},
+    sync::Arc,
+};
 
 use intbits::Bits;
 
 use crate::{
     arm7tdmi::{Cpu, Exception},
-    bus::Bus,
-    video::reg::ModeType,
+    bus::{Bus, BusMut},
 };
 
-use self::reg::{BackgroundControl, DisplayControl, DisplayStatus};
+pub use self::{blend::FrameBlendMode, colour::ColourProfile};
+
+use self::{
+    blend::FrameBlender,
+    obj::{ObjMode, Object, OBJ_COUNT},
+    reg::{
+        AffineBackground, BackgroundControl, BlendControl, ColourEffect, DisplayControl,
+        DisplayStatus, ModeType, Mosaic, WindowBounds, WindowControl,
+    },
+    thread::RenderThread,
+};
 
 pub const FRAME_WIDTH: usize = HBLANK_DOT as _;
 pub const FRAME_HEIGHT: usize = VBLANK_DOT as _;
@@ -38,6 +55,62 @@ impl IndexMut<(usize, usize)> for FrameBuffer {
     }
 }
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Layer {
+    Bg(usize),
+    Obj,
+    Backdrop,
+}
+
+impl Layer {
+    /// Index of the layer's bit in the target selections of `BLDCNT`.
+    fn blend_target_idx(self) -> usize {
+        match self {
+            Self::Bg(bg) => bg,
+            Self::Obj => 4,
+            Self::Backdrop => 5,
+        }
+    }
+}
+
+/// The colour of a layer at a dot.
+#[derive(Copy, Clone, Debug)]
+struct LayerDot {
+    layer: Layer,
+    colour: u16,
+    /// Set for dots of semi-transparent OBJs, which are always alpha blended if possible.
+    semi_transparent: bool,
+}
+
+/// The colours of each layer for the dots of the current scanline, which are composited together
+/// once a range of dots has been drawn.
+struct LineBuffers {
+    bg: [[Option<u16>; FRAME_WIDTH]; 4],
+    /// The priority and colour of the top-most OBJ dot.
+    obj: [Option<(u8, LayerDot)>; FRAME_WIDTH],
+    obj_window: [bool; FRAME_WIDTH],
+    /// The composited RGB555 colours of the dots.
+    colour: [u16; FRAME_WIDTH],
+}
+
+impl Default for LineBuffers {
+    fn default() -> Self {
+        Self {
+            bg: [[None; FRAME_WIDTH]; 4],
+            obj: [None; FRAME_WIDTH],
+            obj_window: [false; FRAME_WIDTH],
+            colour: [0; FRAME_WIDTH],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum VideoMemory {
+    Palette,
+    Vram,
+    Oam,
+}
+
 pub trait Screen {
     fn present_frame(&mut self, frame_buf: &FrameBuffer);
 }
@@ -52,6 +125,13 @@ const CYCLES_PER_DOT: u8 = 4;
 
 pub(super) struct VideoController {
     frame_buf: FrameBuffer,
+    line: Box<LineBuffers>,
+    /// The number of dots of the current scanline that have been drawn.
+    rendered_x: usize,
+    render_thread: Option<RenderThread>,
+    /// The 24-bit colours of every RGB555 colour for the current colour profile.
+    colour_table: Arc<[u32]>,
+    frame_blender: Option<FrameBlender>,
     cycle_accum: u8,
     x: u16,
     y: u8,
@@ -64,6 +144,20 @@ pub(super) struct VideoController {
     pub(super) dispstat: DisplayStatus,
     pub(super) green_swap: u16,
     pub(super) bgcnt: [BackgroundControl; 4],
+    pub(super) bghofs: [u16; 4],
+    pub(super) bgvofs: [u16; 4],
+    pub(super) bgaffine: [AffineBackground; 2],
+    pub(super) win_bounds: [WindowBounds; 2],
+    pub(super) winin: [WindowControl; 2],
+    pub(super) winout: WindowControl,
+    pub(super) objwin: WindowControl,
+    pub(super) bldcnt: BlendControl,
+    pub(super) bldalpha: [u8; 2],
+    pub(super) bldy: u8,
+    pub(super) mosaic: Mosaic,
+    /// The last scanlines latched by the BG and OBJ vertical mosaic counters.
+    bg_mosaic_y: u8,
+    obj_mosaic_y: u8,
 }
 
 impl Default for VideoController {
@@ -72,19 +166,49 @@ impl Default for VideoController {
     }
 }
 
-#[allow(clippy::cast_possible_truncation)]
-fn rgb555_to_24(value: u16) -> u32 {
-    let r = value.bits(..5) as u8;
-    let g = value.bits(5..10) as u8;
-    let b = value.bits(10..15) as u8;
+fn rgb555_channels(value: u16) -> [u16; 3] {
+    [value.bits(..5), value.bits(5..10), value.bits(10..15)]
+}
+
+fn rgb555_from_channels([r, g, b]: [u16; 3]) -> u16 {
+    r | (g << 5) | (b << 10)
+}
+
+const TILE_DIMENSION: usize = 8;
 
-    u32::from_le_bytes([r * 8, g * 8, b * 8, 0])
+/// Number of bytes used by each tile in VRAM.
+fn tile_len(color256: bool) -> usize {
+    if color256 {
+        64
+    } else {
+        32
+    }
+}
+
+/// Returns the palette index of the dot at (`dot_x`, `dot_y`) in the 4 or 8-bit depth tile at
+/// `tile_offset` of `vram`. Dots outside of `vram` read as 0.
+fn tile_dot(vram: &[u8], tile_offset: usize, color256: bool, dot_x: usize, dot_y: usize) -> u8 {
+    if color256 {
+        let offset = tile_offset + TILE_DIMENSION * dot_y + dot_x;
+
+        vram.get(offset).copied().unwrap_or(0)
+    } else {
+        let offset = tile_offset + TILE_DIMENSION / 2 * dot_y + dot_x / 2;
+        let dots = vram.get(offset).copied().unwrap_or(0);
+
+        (dots >> (4 * (dot_x % 2))).bits(..4)
+    }
 }
 
 impl VideoController {
     pub fn new() -> Self {
         Self {
             frame_buf: FrameBuffer::default(),
+            line: Box::default(),
+            rendered_x: 0,
+            render_thread: None,
+            colour_table: ColourProfile::Raw.table().into(),
+            frame_blender: None,
             cycle_accum: 0,
             x: 0,
             y: 0,
@@ -95,22 +219,47 @@ impl VideoController {
             dispstat: DisplayStatus::default(),
             green_swap: 0,
             bgcnt: [BackgroundControl::default(); 4],
+            bghofs: [0; 4],
+            bgvofs: [0; 4],
+            bgaffine: [AffineBackground::default(); 2],
+            win_bounds: [WindowBounds::default(); 2],
+            winin: [WindowControl::default(); 2],
+            winout: WindowControl::default(),
+            objwin: WindowControl::default(),
+            bldcnt: BlendControl::default(),
+            bldalpha: [0; 2],
+            bldy: 0,
+            mosaic: Mosaic::default(),
+            bg_mosaic_y: 0,
+            obj_mosaic_y: 0,
         }
     }
 
     pub fn step(&mut self, screen: &mut impl Screen, cpu: &mut Cpu, cycles: u32) {
         for _ in 0..cycles {
-            if self.x < HBLANK_DOT && self.y < VBLANK_DOT {
-                self.frame_buf[(self.x.into(), self.y.into())] = self.compute_colour();
-            }
-
             self.cycle_accum += 1;
             if self.cycle_accum >= CYCLES_PER_DOT {
                 self.cycle_accum = 0;
                 self.x += 1;
 
-                if self.x == HBLANK_DOT && self.y == VBLANK_DOT - 1 {
-                    screen.present_frame(&self.frame_buf);
+                // Scanlines are drawn all at once when HBlank starts, unless part of it was already
+                // drawn because of a write made mid-scanline. The affine reference points are then
+                // advanced, so that writes to them during HBlank are used as-is by the next line.
+                if self.x == HBLANK_DOT && self.y < VBLANK_DOT {
+                    self.catch_up();
+                    self.bgaffine
+                        .iter_mut()
+                        .for_each(AffineBackground::advance_ref_point);
+
+                    if self.y == VBLANK_DOT - 1 {
+                        if let Some(thread) = &mut self.render_thread {
+                            self.frame_buf = thread.finish_frame();
+                        }
+                        match &mut self.frame_blender {
+                            Some(blender) => screen.present_frame(blender.blend(&self.frame_buf)),
+                            None => screen.present_frame(&self.frame_buf),
+                        }
+                    }
                 }
 
                 let mut irq =
@@ -118,11 +267,18 @@ impl VideoController {
 
                 if self.x >= HORIZ_DOTS {
                     self.x = 0;
+                    self.rendered_x = 0;
                     self.y += 1;
-
                     if self.y >= VERT_DOTS {
                         self.y = 0;
                     }
+                    for bgaffine in &mut self.bgaffine {
+                        if self.y == VBLANK_DOT {
+                            bgaffine.reload_ref_point();
+                        }
+                        bgaffine.latch_ref_point();
+                    }
+                    self.update_mosaic_y();
 
                     irq |= self.dispstat.vblank_irq_enabled && self.y == VBLANK_DOT;
                     irq |=
@@ -136,84 +292,495 @@ impl VideoController {
         }
     }
 
-    fn compute_colour(&self) -> u32 {
-        const TILE_DIMENSION: usize = 8;
+    /// Draws the dots of the current scanline before the current dot that haven't been drawn yet.
+    /// This must be called before writes to the video registers and memory, so that writes made
+    /// mid-scanline only affect the dots after them.
+    pub(super) fn catch_up(&mut self) {
+        let x = usize::from(self.x).min(FRAME_WIDTH);
+        if self.y < VBLANK_DOT && x > self.rendered_x {
+            self.render_dots(self.rendered_x..x);
+        }
+    }
+
+    /// Composes scanlines on a background thread from now on, rather than on the calling thread.
+    pub fn enable_render_thread(&mut self) {
+        if self.render_thread.is_none() {
+            self.render_thread = Some(RenderThread::spawn(self));
+        }
+    }
 
-        if self.dispcnt.forced_blank {
-            return 0xff_ff_ff;
+    pub fn set_colour_profile(&mut self, profile: ColourProfile) {
+        self.colour_table = profile.table().into();
+        if let Some(thread) = &mut self.render_thread {
+            thread.set_colour_table(Arc::clone(&self.colour_table));
         }
+    }
 
-        // TODO: palette colour 0 is always transparent
-        match self.dispcnt.mode_type() {
-            ModeType::Tile => {
-                // TODO: other BGs, other sizes than 256, proper flipping, etc.
-                let tile_x = usize::from(self.x) / TILE_DIMENSION;
-                let tile_y = usize::from(self.y) / TILE_DIMENSION;
-                let tile_idx = tile_y * (256 / TILE_DIMENSION) + tile_x;
-                let tile_info_offset = self.bgcnt[0].vram_offset() + 2 * tile_idx;
+    pub fn set_frame_blend_mode(&mut self, mode: Option<FrameBlendMode>) {
+        self.frame_blender = mode.map(FrameBlender::new);
+    }
 
-                #[allow(clippy::cast_possible_truncation)]
-                let tile_info = self.vram.as_ref().read_hword(tile_info_offset as u32);
-                let dots_idx = usize::from(tile_info.bits(..10));
-                let flip_horiz = tile_info.bit(10);
-                let flip_vert = tile_info.bit(11);
-
-                let mut dot_x = usize::from(self.x) % TILE_DIMENSION;
-                if flip_horiz {
-                    dot_x = TILE_DIMENSION - dot_x;
-                }
-                let mut dot_y = usize::from(self.y) % TILE_DIMENSION;
-                if flip_vert {
-                    dot_y = TILE_DIMENSION - dot_y;
-                }
+    /// Writes a hword to video memory. Writes must be made through here if the render thread is
+    /// enabled, so that its copy of video memory is kept in sync.
+    pub(super) fn write_hword(&mut self, mem: VideoMemory, offset: u32, value: u16) {
+        let mut buf = match mem {
+            VideoMemory::Palette => self.palette_ram.as_mut(),
+            VideoMemory::Vram => self.vram.as_mut(),
+            VideoMemory::Oam => self.oam.as_mut(),
+        };
+        buf.write_hword(offset, value);
 
-                if self.bgcnt[0].color256 {
-                    0x00_ff_00 // TODO
-                } else {
-                    // 4-bit depth
-                    let palette_group_idx = usize::from(tile_info.bits(12..));
-                    let dots_base_offset = self.bgcnt[0].dots_vram_offset() + 32 * dots_idx;
-                    let dots = self.vram[dots_base_offset + (4 * dot_y) + (dot_x / 2)];
-                    let palette_idx = usize::from(dots >> (4 * (dot_x % 2))).bits(..4);
-                    #[allow(clippy::cast_possible_truncation)]
-                    let palette_offset = 2 * (16 * palette_group_idx + palette_idx) as u32;
-
-                    rgb555_to_24(self.palette_ram.as_ref().read_hword(palette_offset))
+        if let Some(thread) = &mut self.render_thread {
+            thread.write_hword(mem, offset, value);
+        }
+    }
+
+    /// Draws a range of dots of the current scanline, or queues them to be drawn by the render
+    /// thread if it is enabled.
+    fn render_dots(&mut self, dots: Range<usize>) {
+        self.rendered_x = dots.end;
+        if let Some(mut thread) = self.render_thread.take() {
+            thread.render(self, dots);
+            self.render_thread = Some(thread);
+        } else {
+            self.draw_dots(dots);
+        }
+    }
+
+    /// Draws a range of dots of the current scanline to the frame buffer.
+    fn draw_dots(&mut self, dots: Range<usize>) {
+        let y = usize::from(self.y);
+        if self.dispcnt.forced_blank {
+            self.line.colour[dots.clone()].fill(0x7fff);
+        } else {
+            for bg in (0..4).filter(|&bg| self.dispcnt.display_bg[bg]) {
+                for x in dots.clone() {
+                    self.line.bg[bg][x] = self.bg_colour(bg, x, y);
                 }
             }
-            ModeType::Bitmap if self.dispcnt.display_bg[2] => {
-                let (dot_x, dot_y) = (usize::from(self.x), usize::from(self.y));
+            self.render_obj_line(dots.clone());
+
+            for x in dots.clone() {
+                self.line.colour[x] = self.composite(x, y);
+            }
+        }
 
-                match self.dispcnt.mode {
-                    3 => {
-                        let dot_idx = dot_y * FRAME_WIDTH + dot_x;
+        // Dots are output in pairs once both have been composited, as green swap swaps the green
+        // components of each pair.
+        for x in dots.filter(|x| x % 2 == 1) {
+            let [mut left, mut right] = [self.line.colour[x - 1], self.line.colour[x]];
+            if self.green_swap.bit(0) {
+                (left, right) = (
+                    left.with_bits(5..10, right.bits(5..10)),
+                    right.with_bits(5..10, left.bits(5..10)),
+                );
+            }
 
-                        #[allow(clippy::cast_possible_truncation)]
-                        rgb555_to_24(self.vram.as_ref().read_hword(2 * dot_idx as u32))
-                    }
-                    4 => {
-                        let dot_idx =
-                            self.dispcnt.frame_vram_offset() + dot_y * FRAME_WIDTH + dot_x;
-                        // TODO: colour 0 is the backdrop colour, and also acts as transparent when
-                        //       rendering the object layer
-                        let palette_offset = u32::from(2 * self.vram[dot_idx]);
+            self.frame_buf[(x - 1, y)] = self.colour_table[usize::from(left.bits(..15))];
+            self.frame_buf[(x, y)] = self.colour_table[usize::from(right.bits(..15))];
+        }
+    }
 
-                        rgb555_to_24(self.palette_ram.as_ref().read_hword(palette_offset))
-                    }
-                    5 => {
-                        // TODO: this is actually 160x128 pixels, we probably want to rescale...
-                        let dot_idx = self.dispcnt.frame_vram_offset() + dot_y * 160 + dot_x;
+    /// Draws the OBJs covering a range of dots of the current scanline to the line buffers. OBJs
+    /// with lower OAM indices are drawn on top of others with the same priority.
+    fn render_obj_line(&mut self, dots: Range<usize>) {
+        let y = usize::from(self.y);
+        self.line.obj[dots.clone()].fill(None);
+        self.line.obj_window[dots.clone()].fill(false);
+        if !self.dispcnt.display_obj {
+            return;
+        }
+
+        for i in 0..OBJ_COUNT {
+            let obj = Object::from_oam(&self.oam, i);
+            let line_y = if obj.mosaic {
+                usize::from(self.obj_mosaic_y)
+            } else {
+                y
+            };
+            if !obj.is_displayed() || obj.dot_pos(obj.x.into(), line_y).is_none() {
+                continue;
+            }
 
-                        let _ = dot_idx;
-                        todo!();
+            let (width, _) = obj.bounds();
+            for x in (0..width)
+                .map(|dot_x| (usize::from(obj.x) + dot_x) % 512)
+                .filter(|x| dots.contains(x))
+            {
+                let colour = self.obj_dot_colour(&obj, x, y);
+                let is_below =
+                    matches!(self.line.obj[x], Some((priority, _)) if priority <= obj.priority);
+
+                match colour {
+                    None => {}
+                    // OBJ window OBJs aren't displayed, but make up the OBJ window instead.
+                    Some(_) if obj.mode == ObjMode::Window => self.line.obj_window[x] = true,
+                    Some(_) if is_below => {}
+                    Some(colour) => {
+                        let dot = LayerDot {
+                            layer: Layer::Obj,
+                            colour,
+                            semi_transparent: obj.mode == ObjMode::SemiTransparent,
+                        };
+                        self.line.obj[x] = Some((obj.priority, dot));
                     }
-                    _ => unreachable!(),
                 }
             }
-            _ => 0xff_ff_ff,
         }
     }
 
+    /// Composites the layers in the line buffers at the dot (`x`, `y`) on the screen, applying
+    /// colour effects, and returns the RGB555 colour of the dot.
+    fn composite(&self, x: usize, y: usize) -> u16 {
+        let window = self.window_control(x, y);
+        let [top, below] = self.top_layers(x, window);
+        if !window.effects {
+            return top.colour;
+        }
+
+        let is_target =
+            |i: usize, dot: LayerDot| self.bldcnt.targets[i][dot.layer.blend_target_idx()];
+        if top.semi_transparent && is_target(1, below) {
+            self.alpha_blend(top.colour, below.colour)
+        } else if is_target(0, top) {
+            match self.bldcnt.effect {
+                ColourEffect::AlphaBlend if is_target(1, below) => {
+                    self.alpha_blend(top.colour, below.colour)
+                }
+                ColourEffect::Brighten => self.fade(top.colour, 31),
+                ColourEffect::Darken => self.fade(top.colour, 0),
+                _ => top.colour,
+            }
+        } else {
+            top.colour
+        }
+    }
+
+    /// Blends two RGB555 colours using the EVA and EVB coefficients of `BLDALPHA`.
+    fn alpha_blend(&self, top: u16, below: u16) -> u16 {
+        let [eva, evb] = self.bldalpha.map(|ev| u16::from(ev.min(16)));
+        let (top, below) = (rgb555_channels(top), rgb555_channels(below));
+
+        rgb555_from_channels([0, 1, 2].map(|i| ((top[i] * eva + below[i] * evb) / 16).min(31)))
+    }
+
+    /// Fades an RGB555 colour towards the intensity `target` (31 for white, 0 for black) using the
+    /// EVY coefficient of `BLDY`.
+    fn fade(&self, colour: u16, target: u16) -> u16 {
+        let evy = u16::from(self.bldy.min(16));
+
+        rgb555_from_channels(rgb555_channels(colour).map(|c| {
+            if target > c {
+                c + (target - c) * evy / 16
+            } else {
+                c - (c - target) * evy / 16
+            }
+        }))
+    }
+
+    /// Returns the layers and effects enabled at the dot (`x`, `y`) on the screen by the window
+    /// that contains it. WIN0 has the highest priority, followed by WIN1, then the OBJ window, which
+    /// is read from the line buffers.
+    fn window_control(&self, x: usize, y: usize) -> WindowControl {
+        let [win0, win1] = self.dispcnt.display_window;
+        let objwin = self.dispcnt.display_obj_window && self.dispcnt.display_obj;
+        if !win0 && !win1 && !objwin {
+            return WindowControl::all();
+        }
+
+        if win0 && self.win_bounds[0].contains(x, y) {
+            self.winin[0]
+        } else if win1 && self.win_bounds[1].contains(x, y) {
+            self.winin[1]
+        } else if objwin && self.line.obj_window[x] {
+            self.objwin
+        } else {
+            self.winout
+        }
+    }
+
+    /// Returns the top two visible layers in the line buffers at `x`, from the top-most. Layers
+    /// with lower priority values are drawn on top, with OBJs drawn above backgrounds of the same
+    /// priority, and lower numbered backgrounds above others of the same priority. The backdrop is
+    /// below all other layers, and is always visible.
+    fn top_layers(&self, x: usize, window: WindowControl) -> [LayerDot; 2] {
+        let obj = window.display_obj.then_some(self.line.obj[x]).flatten();
+        let backdrop = LayerDot {
+            layer: Layer::Backdrop,
+            colour: self.backdrop_colour(),
+            semi_transparent: false,
+        };
+
+        let mut layers = (0..4)
+            .flat_map(|priority| {
+                let obj = obj
+                    .filter(|&(obj_priority, _)| obj_priority == priority)
+                    .map(|(_, dot)| dot);
+                let bgs = (0..4)
+                    .filter(move |&bg| {
+                        self.dispcnt.display_bg[bg]
+                            && window.display_bg[bg]
+                            && self.bgcnt[bg].priority == priority
+                    })
+                    .filter_map(move |bg| {
+                        Some(LayerDot {
+                            layer: Layer::Bg(bg),
+                            colour: self.line.bg[bg][x]?,
+                            semi_transparent: false,
+                        })
+                    });
+
+                obj.into_iter().chain(bgs)
+            })
+            .chain(iter::once(backdrop));
+
+        [layers.next().unwrap(), layers.next().unwrap_or(backdrop)]
+    }
+
+    /// Returns the RGB555 colour of a background at the dot (`x`, `y`) on the screen, or `None` if
+    /// it is transparent or not used by the current mode.
+    fn bg_colour(&self, bg: usize, x: usize, y: usize) -> Option<u16> {
+        // With mosaic, each dot takes the colour of the top-left dot of its block.
+        let (x, y) = if self.bgcnt[bg].mosaic {
+            let width = usize::from(self.mosaic.bg[0]) + 1;
+            (x - x % width, usize::from(self.bg_mosaic_y))
+        } else {
+            (x, y)
+        };
+        let lines_back = usize::from(self.y).saturating_sub(y);
+
+        match (self.dispcnt.mode, bg) {
+            (0, _) | (1, 0 | 1) => self.text_bg_colour(bg, x, y),
+            (1, 2) | (2, 2 | 3) => self.affine_bg_colour(bg, x, lines_back),
+            (3..=5, 2) => self.bitmap_bg_colour(x, lines_back),
+            _ => None,
+        }
+    }
+
+    /// Latches the current scanline for vertical mosaic every time the mosaic height has been
+    /// reached since the last latch, counting from the start of the frame. Lines between latches
+    /// are drawn as copies of the latched line.
+    fn update_mosaic_y(&mut self) {
+        let y = self.y;
+        let update = |mosaic_y: &mut u8, height: u8| {
+            if y == 0 || y - *mosaic_y > height {
+                *mosaic_y = y;
+            }
+        };
+
+        update(&mut self.bg_mosaic_y, self.mosaic.bg[1]);
+        update(&mut self.obj_mosaic_y, self.mosaic.obj[1]);
+    }
+
+    /// Returns the RGB555 colour of `obj` at the dot (`x`, `y`) on the screen, or `None` if it is
+    /// transparent or not covered by it.
+    fn obj_dot_colour(&self, obj: &Object, x: usize, y: usize) -> Option<u16> {
+        let (width, height) = obj.dimensions();
+        let (dot_x, dot_y) = if obj.mosaic {
+            // OBJ mosaic blocks start from the left of each OBJ.
+            let mosaic_width = usize::from(self.mosaic.obj[0]) + 1;
+            let (dot_x, dot_y) = obj.dot_pos(x, self.obj_mosaic_y.into())?;
+
+            (dot_x - dot_x % mosaic_width, dot_y)
+        } else {
+            obj.dot_pos(x, y)?
+        };
+        let (dot_x, dot_y) = if obj.affine {
+            // Rotation and scaling is relative to the centre of the OBJ, and dots that map to
+            // outside of the OBJ are transparent.
+            let [pa, pb, pc, pd] = obj::affine_params(&self.oam, obj.affine_params_idx);
+            let (bounds_width, bounds_height) = obj.bounds();
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            let (rel_x, rel_y) = (
+                (dot_x as i32) - (bounds_width / 2) as i32,
+                (dot_y as i32) - (bounds_height / 2) as i32,
+            );
+
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            let (tex_x, tex_y) = (
+                ((i32::from(pa) * rel_x + i32::from(pb) * rel_y) >> 8) + (width / 2) as i32,
+                ((i32::from(pc) * rel_x + i32::from(pd) * rel_y) >> 8) + (height / 2) as i32,
+            );
+            let tex_x = usize::try_from(tex_x).ok().filter(|&x| x < width)?;
+            let tex_y = usize::try_from(tex_y).ok().filter(|&y| y < height)?;
+
+            (tex_x, tex_y)
+        } else {
+            let (mut dot_x, mut dot_y) = (dot_x, dot_y);
+            if obj.flip_horiz {
+                dot_x = width - 1 - dot_x;
+            }
+            if obj.flip_vert {
+                dot_y = height - 1 - dot_y;
+            }
+
+            (dot_x, dot_y)
+        };
+
+        // Tile numbers are in units of 32 bytes, even for 8-bit depth tiles. With 2D mapping, the
+        // tiles are laid out as a 32x32 grid of 4-bit depth tiles; with 1D mapping, the tiles of
+        // each row of the OBJ follow each other.
+        let tile_step = tile_len(obj.color256) / 32;
+        let (tile_x, tile_y) = (dot_x / TILE_DIMENSION, dot_y / TILE_DIMENSION);
+        let tile_idx = if self.dispcnt.obj_1d {
+            usize::from(obj.tile_idx) + (tile_y * width / TILE_DIMENSION + tile_x) * tile_step
+        } else {
+            let base_idx = usize::from(obj.tile_idx) & !(tile_step - 1);
+            base_idx + tile_y * 32 + tile_x * tile_step
+        } % 1024;
+
+        // In bitmap modes, the first half of OBJ VRAM is used by the bitmap instead.
+        if self.dispcnt.mode_type() == ModeType::Bitmap && tile_idx < 512 {
+            return None;
+        }
+
+        let (dot_x, dot_y) = (dot_x % TILE_DIMENSION, dot_y % TILE_DIMENSION);
+        let palette_idx = match tile_dot(
+            &self.vram[0x1_0000..],
+            32 * tile_idx,
+            obj.color256,
+            dot_x,
+            dot_y,
+        ) {
+            0 => return None,
+            dot if obj.color256 => usize::from(dot),
+            dot => 16 * usize::from(obj.palette_idx) + usize::from(dot),
+        };
+
+        Some(self.obj_palette_colour(palette_idx))
+    }
+
+    fn backdrop_colour(&self) -> u16 {
+        self.bg_palette_colour(0)
+    }
+
+    /// Returns the RGB555 colour of the dot at (`x`, `y`) on the screen for a text background, or
+    /// `None` if it is transparent.
+    fn text_bg_colour(&self, bg: usize, x: usize, y: usize) -> Option<u16> {
+        let bgcnt = self.bgcnt[bg];
+        let (width, height) = bgcnt.text_screen_size();
+        let x = (x + usize::from(self.bghofs[bg])) % width;
+        let y = (y + usize::from(self.bgvofs[bg])) % height;
+
+        // Maps larger than 256x256 are made up of multiple 32x32 tile screen blocks.
+        let block_idx = x / 256 + (y / 256) * (width / 256);
+        let tile_idx = (y % 256 / TILE_DIMENSION) * 32 + (x % 256 / TILE_DIMENSION);
+        let tile_info_offset = bgcnt.vram_offset() + 0x800 * block_idx + 2 * tile_idx;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let tile_info = self.vram.as_ref().read_hword(tile_info_offset as u32);
+        let dots_idx = usize::from(tile_info.bits(..10));
+        let flip_horiz = tile_info.bit(10);
+        let flip_vert = tile_info.bit(11);
+
+        let mut dot_x = x % TILE_DIMENSION;
+        if flip_horiz {
+            dot_x = TILE_DIMENSION - 1 - dot_x;
+        }
+        let mut dot_y = y % TILE_DIMENSION;
+        if flip_vert {
+            dot_y = TILE_DIMENSION - 1 - dot_y;
+        }
+
+        // Tile numbers may go past the end of the character base block into the next ones, but
+        // backgrounds can only access the first 64 KiB of VRAM in tile modes; the rest is for OBJs.
+        let tile_offset = bgcnt.dots_vram_offset() + dots_idx * tile_len(bgcnt.color256);
+        let dot = tile_dot(
+            &self.vram[..0x1_0000],
+            tile_offset,
+            bgcnt.color256,
+            dot_x,
+            dot_y,
+        );
+        if dot == 0 {
+            return None; // Colour 0 is transparent
+        }
+
+        let palette_idx = if bgcnt.color256 {
+            usize::from(dot)
+        } else {
+            16 * usize::from(tile_info.bits(12..)) + usize::from(dot)
+        };
+
+        Some(self.bg_palette_colour(palette_idx))
+    }
+
+    /// Returns the RGB555 colour of the dot at `x` on the current scanline for an affine background
+    /// (BG2 or BG3), or `None` if it is transparent.
+    fn affine_bg_colour(&self, bg: usize, x: usize, lines_back: usize) -> Option<u16> {
+        let bgcnt = self.bgcnt[bg];
+        let size = bgcnt.affine_screen_size();
+        let (x, y) = self.bgaffine[bg - 2].transform(x, lines_back);
+
+        // Dots outside of the map are transparent, unless it wraps around.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let wrap = |pos: i32| {
+            let pos = pos >> 8;
+            if bgcnt.wraparound {
+                Some(pos.rem_euclid(size as i32) as usize)
+            } else {
+                usize::try_from(pos).ok().filter(|&pos| pos < size)
+            }
+        };
+        let (x, y) = (wrap(x)?, wrap(y)?);
+
+        // Affine maps are made up of byte-sized tile numbers of 8-bit depth tiles.
+        let tile_info_offset = bgcnt.vram_offset() + (y / TILE_DIMENSION) * (size / TILE_DIMENSION);
+        let dots_idx = usize::from(self.vram[tile_info_offset + x / TILE_DIMENSION]);
+        let tile_offset = bgcnt.dots_vram_offset() + dots_idx * tile_len(true);
+        let (dot_x, dot_y) = (x % TILE_DIMENSION, y % TILE_DIMENSION);
+
+        match tile_dot(&self.vram[..0x1_0000], tile_offset, true, dot_x, dot_y) {
+            0 => None,
+            dot => Some(self.bg_palette_colour(dot.into())),
+        }
+    }
+
+    /// Returns the RGB555 colour of the dot at `x` on the current scanline for the bitmap of BG2
+    /// in modes 3 to 5, or `None` if it is transparent. Like affine backgrounds, the bitmap is
+    /// drawn using the BG2 rotation and scaling parameters.
+    fn bitmap_bg_colour(&self, x: usize, lines_back: usize) -> Option<u16> {
+        let (width, height) = if self.dispcnt.mode == 5 {
+            (160, 128)
+        } else {
+            (FRAME_WIDTH, FRAME_HEIGHT)
+        };
+
+        // Unlike affine backgrounds, bitmaps never wrap around.
+        let (x, y) = self.bgaffine[0].transform(x, lines_back);
+        let x = usize::try_from(x >> 8).ok().filter(|&x| x < width)?;
+        let y = usize::try_from(y >> 8).ok().filter(|&y| y < height)?;
+        let dot_idx = y * width + x;
+
+        match self.dispcnt.mode {
+            3 =>
+            {
+                #[allow(clippy::cast_possible_truncation)]
+                Some(self.vram.as_ref().read_hword(2 * dot_idx as u32))
+            }
+            4 => match self.vram[self.dispcnt.frame_vram_offset() + dot_idx] {
+                0 => None,
+                dot => Some(self.bg_palette_colour(dot.into())),
+            },
+            5 => {
+                #[allow(clippy::cast_possible_truncation)]
+                let offset = (self.dispcnt.frame_vram_offset() + 2 * dot_idx) as u32;
+
+                Some(self.vram.as_ref().read_hword(offset))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn bg_palette_colour(&self, idx: usize) -> u16 {
+        #[allow(clippy::cast_possible_truncation)]
+        self.palette_ram.as_ref().read_hword(2 * idx as u32)
+    }
+
+    fn obj_palette_colour(&self, idx: usize) -> u16 {
+        self.bg_palette_colour(256 + idx)
+    }
+
     pub(super) fn dispstat_lo_bits(&self) -> u8 {
         self.dispstat.lo_bits(
             self.y >= VBLANK_DOT && self.y != 227,
@@ -226,3 +793,867 @@ impl VideoController {
         self.y
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read};
+
+    use flate2::read::ZlibDecoder;
+
+    use crate::bus::BusMut;
+
+    use super::{colour::rgb555_to_24, *};
+
+    /// Draws the current dot, returning its colour before being output to the frame buffer.
+    fn render_dot(video: &mut VideoController) -> u32 {
+        let x = usize::from(video.x);
+        video.render_dots(x..x + 1);
+
+        rgb555_to_24(video.line.colour[x])
+    }
+
+    /// Disables every OBJ but the first, which the tests set up themselves.
+    fn disable_objs(video: &mut VideoController) {
+        for i in 1..OBJ_COUNT {
+            #[allow(clippy::cast_possible_truncation)]
+            video.oam.as_mut().write_hword(8 * i as u32, 0x0200);
+        }
+    }
+
+    struct TestScreen(Option<Box<[u32]>>);
+
+    impl Screen for TestScreen {
+        fn present_frame(&mut self, frame_buf: &FrameBuffer) {
+            self.0 = Some(frame_buf.0.clone());
+        }
+    }
+
+    /// Steps through a frame from the start of the last scanline of the previous one, returning
+    /// the presented frame. `on_dot` is called before each dot, and may write to the registers and
+    /// memory like the CPU would mid-scanline or during `HBlank`.
+    fn render_frame(
+        video: &mut VideoController,
+        mut on_dot: impl FnMut(&mut VideoController),
+    ) -> Box<[u32]> {
+        let mut screen = TestScreen(None);
+        let mut cpu = Cpu::new();
+        video.x = 0;
+        video.y = VERT_DOTS - 1;
+        video.cycle_accum = 0;
+        while screen.0.is_none() {
+            video.catch_up();
+            on_dot(video);
+            video.step(&mut screen, &mut cpu, CYCLES_PER_DOT.into());
+        }
+
+        screen.0.unwrap()
+    }
+
+    /// Compares a frame against a golden image, given as the RGB555 colour of each dot.
+    fn assert_frame_eq(golden: impl Fn(usize, usize) -> u16, frame: &[u32]) {
+        for y in 0..FRAME_HEIGHT {
+            for x in 0..FRAME_WIDTH {
+                let actual = frame[y * FRAME_WIDTH + x];
+                assert_eq!(rgb555_to_24(golden(x, y)), actual, "dot ({x}, {y})");
+            }
+        }
+    }
+
+    /// Loads the frame from one of the gba-tests PPU result screenshots in the media directory,
+    /// as the RGB555 colour of each dot. These are 8-bit RGBA PNGs of a whole window, with the
+    /// frame at (10, 45) and channels that are the RGB555 ones shifted left by 3.
+    fn load_gba_tests_golden(name: &str) -> Vec<u16> {
+        let path = format!(
+            "{}/media/gba-tests-ppu-{name}-result.png",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let png = fs::read(path).unwrap();
+        assert_eq!(b"\x89PNG\r\n\x1a\n", &png[..8]);
+
+        let (mut width, mut idat, mut offset) = (0, Vec::new(), 8);
+        while offset < png.len() {
+            let len = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+            let data = &png[offset + 8..offset + 8 + len];
+            match &png[offset + 4..offset + 8] {
+                b"IHDR" => {
+                    width = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+                    assert_eq!([8, 6, 0, 0, 0], data[8..], "expected non-interlaced RGBA8");
+                }
+                b"IDAT" => idat.extend_from_slice(data),
+                _ => {}
+            }
+            offset += len + 12;
+        }
+
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&idat[..]).read_to_end(&mut raw).unwrap();
+
+        // Undo the filter of each row; see the PNG specification.
+        let stride = 4 * width;
+        let mut rows: Vec<Vec<u8>> = Vec::new();
+        for filtered in raw.chunks(stride + 1) {
+            let mut row = filtered[1..].to_vec();
+            let prev = rows.last().cloned().unwrap_or_else(|| vec![0; stride]);
+            for i in 0..stride {
+                let (a, b) = (if i >= 4 { row[i - 4] } else { 0 }, prev[i]);
+                let c = if i >= 4 { prev[i - 4] } else { 0 };
+                let predicted = match filtered[0] {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => a / 2 + b / 2 + (a & b & 1),
+                    4 => {
+                        let p = i16::from(a) + i16::from(b) - i16::from(c);
+                        let (pa, pb, pc) = (
+                            (p - i16::from(a)).abs(),
+                            (p - i16::from(b)).abs(),
+                            (p - i16::from(c)).abs(),
+                        );
+                        if pa <= pb && pa <= pc {
+                            a
+                        } else if pb <= pc {
+                            b
+                        } else {
+                            c
+                        }
+                    }
+                    filter => panic!("unknown filter type {filter}"),
+                };
+                row[i] = row[i].wrapping_add(predicted);
+            }
+            rows.push(row);
+        }
+
+        let mut frame = Vec::with_capacity(FRAME_WIDTH * FRAME_HEIGHT);
+        for row in &rows[45..45 + FRAME_HEIGHT] {
+            for rgba in row[4 * 10..].chunks(4).take(FRAME_WIDTH) {
+                frame.push(rgb555_from_channels(
+                    [rgba[0], rgba[1], rgba[2]].map(|c| u16::from(c >> 3)),
+                ));
+            }
+        }
+
+        frame
+    }
+
+    #[test]
+    fn catch_up_works() {
+        let mut video = VideoController::new();
+        video.palette_ram.as_mut().write_hword(0, 0x1111);
+
+        // A write made mid-scanline only affects the dots after it
+        video.x = 100;
+        video.y = 5;
+        video.catch_up();
+        video.palette_ram.as_mut().write_hword(0, 0x2222);
+        video.x = HBLANK_DOT;
+        video.catch_up();
+        assert_eq!(rgb555_to_24(0x1111), video.frame_buf[(0, 5)]);
+        assert_eq!(rgb555_to_24(0x1111), video.frame_buf[(99, 5)]);
+        assert_eq!(rgb555_to_24(0x2222), video.frame_buf[(100, 5)]);
+        assert_eq!(rgb555_to_24(0x2222), video.frame_buf[(239, 5)]);
+
+        // Nothing is drawn during VBlank
+        video.y = VBLANK_DOT;
+        video.rendered_x = 0;
+        video.catch_up();
+        assert_eq!(0, video.rendered_x);
+    }
+
+    #[test]
+    fn shades_raster_works() {
+        // Reproduces the gba-tests PPU shades test, but with the 15 shades of blue drawn by
+        // changing the backdrop colour mid-scanline.
+        let golden = load_gba_tests_golden("shade");
+        let mut video = VideoController::new();
+        #[allow(clippy::cast_possible_truncation)]
+        let shade = |x: usize| (2 * (x / 16) as u16) << 10;
+
+        let frame = render_frame(&mut video, |video| {
+            if video.x < HBLANK_DOT && video.y < VBLANK_DOT {
+                let colour = shade(video.x.into());
+                video.palette_ram.as_mut().write_hword(0, colour);
+            }
+        });
+        assert_frame_eq(|x, y| golden[y * FRAME_WIDTH + x], &frame);
+    }
+
+    #[test]
+    fn stripes_raster_works() {
+        // Reproduces the gba-tests PPU stripes test with writes made like HBlank DMA. The tiles
+        // swap colours on odd rows, which the palette written during each HBlank swaps back, so
+        // any line drawn with the palette meant for another would differ from the screenshot.
+        // BG0 is also scrolled by a stripe mid-scanline on line 80, which should only swap the
+        // colours of the dots after the write.
+        let golden = load_gba_tests_golden("stripes");
+        let mut video = VideoController::new();
+        let colours = [
+            rgb555_from_channels([11, 16, 21]),
+            rgb555_from_channels([16, 20, 24]),
+        ];
+
+        // A map alternating between tiles 0 and 1, with rows of colour 1 and 2 in opposite order
+        video.dispcnt.display_bg[0] = true;
+        video.bgcnt[0].base_block = 1;
+        for row in 0..8 {
+            let [first, second] = if row % 2 == 0 {
+                [0x11, 0x22]
+            } else {
+                [0x22, 0x11]
+            };
+            video.vram[4 * row..][..4].fill(first);
+            video.vram[32 + 4 * row..][..4].fill(second);
+        }
+        for i in 0..32 * 32 {
+            video
+                .vram
+                .as_mut()
+                .write_hword(0x800 + 2 * i, (i & 1).try_into().unwrap());
+        }
+
+        let frame = render_frame(&mut video, |video| {
+            let y = video.y;
+            if video.x == HBLANK_DOT && (y < VBLANK_DOT - 1 || y == VERT_DOTS - 1) {
+                // The registers for the next line, as HBlank DMA would copy them from a table
+                let odd = (y + 1) % VERT_DOTS % 2 == 1;
+                let [first, second] = if odd { [1, 0] } else { [0, 1] };
+                video.palette_ram.as_mut().write_hword(2, colours[first]);
+                video.palette_ram.as_mut().write_hword(4, colours[second]);
+                video.bghofs[0] = 0;
+            } else if video.x == 120 && y == 80 {
+                video.bghofs[0] = 8;
+            }
+        });
+        assert_frame_eq(
+            |x, y| {
+                let colour = golden[y * FRAME_WIDTH + x];
+                match (x, y) {
+                    (120.., 80) if colour == colours[0] => colours[1],
+                    (120.., 80) => colours[0],
+                    _ => colour,
+                }
+            },
+            &frame,
+        );
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn affine_raster_works() {
+        // Each scanline shows the bitmap row given by the reference point written during the
+        // previous HBlank, like with perspective effects, except for line 11: the reference point
+        // is written mid-scanline on line 10 instead, so it only takes effect from the next line,
+        // and is advanced by PD after line 10 is drawn.
+        let mut video = VideoController::new();
+        video.dispcnt.mode = 3;
+        video.dispcnt.display_bg[2] = true;
+        let colour = |x: usize, y: usize| ((x % 32) | ((y % 32) << 5)) as u16;
+        for y in 0..FRAME_HEIGHT {
+            for x in 0..FRAME_WIDTH {
+                let offset = 2 * (y * FRAME_WIDTH + x) as u32;
+                video.vram.as_mut().write_hword(offset, colour(x, y));
+            }
+        }
+
+        let write_ref_y = |video: &mut VideoController, y: i32| {
+            for (i, byte) in (y << 8).to_le_bytes().into_iter().enumerate() {
+                video.bgaffine[0].write_byte(0xc + i as u32, byte);
+            }
+        };
+        let frame = render_frame(&mut video, |video| {
+            let y = i32::from(video.y);
+            if video.x == HBLANK_DOT && video.y < VBLANK_DOT && y != 10 {
+                write_ref_y(video, (y + 1) / 2);
+            } else if video.x == 120 && y == 10 {
+                write_ref_y(video, 50);
+            }
+        });
+        assert_frame_eq(
+            |x, y| match y {
+                11 => colour(x, 51),
+                _ => colour(x, y / 2),
+            },
+            &frame,
+        );
+    }
+
+    #[test]
+    fn render_thread_works() {
+        // Frames drawn on the render thread are the same as when drawn on the calling thread,
+        // including writes to video memory made mid-scanline
+        let render = |threaded| {
+            let mut video = VideoController::new();
+            video.dispcnt.display_bg[0] = true;
+            video.bgcnt[0].base_block = 1;
+            if threaded {
+                video.enable_render_thread();
+            }
+            video.write_hword(VideoMemory::Vram, 0, 0x0021);
+
+            render_frame(&mut video, |video| {
+                let (x, y) = (video.x, u16::from(video.y));
+                if x < HBLANK_DOT && video.y < VBLANK_DOT {
+                    video.write_hword(VideoMemory::Palette, 2 * u32::from(x % 3), x + y);
+                } else if x == HBLANK_DOT {
+                    video.bghofs[0] = y;
+                }
+            })
+        };
+
+        let frame = render(false);
+        assert!(frame.iter().any(|&colour| colour != frame[0]));
+        assert!(frame == render(true));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn green_swap_works() {
+        let mut video = VideoController::new();
+        video.dispcnt.mode = 3;
+        video.dispcnt.display_bg[2] = true;
+        video.green_swap = 1;
+        let colour =
+            |x: usize, y: usize| rgb555_from_channels([y, x, x + y].map(|c| (c % 32) as u16));
+        for y in 0..FRAME_HEIGHT {
+            for x in 0..FRAME_WIDTH {
+                let offset = 2 * (y * FRAME_WIDTH + x) as u32;
+                video.vram.as_mut().write_hword(offset, colour(x, y));
+            }
+        }
+
+        // The green components of each pair of dots are swapped, even though render_frame draws
+        // each dot separately
+        let frame = render_frame(&mut video, |_| {});
+        assert_frame_eq(
+            |x, y| colour(x, y).with_bits(5..10, colour(x ^ 1, y).bits(5..10)),
+            &frame,
+        );
+    }
+
+    #[test]
+    fn text_bg_works() {
+        let mut video = VideoController::new();
+        video.dispcnt.display_bg[0] = true;
+        video.bgcnt[0].set_hi_bits(0b1100_1000); // 512x512, screen base block 8
+        video.bghofs[0] = 256 + 8;
+        video.bgvofs[0] = 256;
+
+        // Backdrop, then colours 1 and 3 of palette 2
+        video.palette_ram.as_mut().write_hword(0, 0x03e0);
+        video.palette_ram.as_mut().write_hword(2 * 33, 0x001f);
+        video.palette_ram.as_mut().write_hword(2 * 35, 0x7c00);
+
+        // Tile 1, horizontally flipped with palette 2, as the second tile of the last screen block
+        video
+            .vram
+            .as_mut()
+            .write_hword(8 * 0x800 + 3 * 0x800 + 2, 0x2401);
+        video.vram[32] = 0x01;
+        video.vram[32 + 3] = 0x30;
+
+        let mut colour_at = |x, y| {
+            video.x = x;
+            video.y = y;
+            render_dot(&mut video)
+        };
+        assert_eq!(rgb555_to_24(0x7c00), colour_at(0, 0));
+        assert_eq!(rgb555_to_24(0x001f), colour_at(7, 0));
+        assert_eq!(rgb555_to_24(0x03e0), colour_at(6, 0));
+        assert_eq!(rgb555_to_24(0x03e0), colour_at(8, 0));
+    }
+
+    #[test]
+    fn text_bg_8bpp_works() {
+        let mut video = VideoController::new();
+        video.dispcnt.display_bg[0] = true;
+        video.bgcnt[0].set_lo_bits(0b1000_1100); // 256 colours, character base block 3
+        video.bgcnt[0].set_hi_bits(0b0000_0001); // Screen base block 1
+        video.palette_ram.as_mut().write_hword(2 * 0xab, 0x1234);
+
+        // Tile 2, then tile 0x200, which is past the end of the background area of VRAM
+        video.vram.as_mut().write_hword(0x800, 2);
+        video.vram.as_mut().write_hword(0x802, 0x200);
+        video.vram[0xc000 + 2 * 64 + 8 + 1] = 0xab;
+
+        let mut colour_at = |x, y| {
+            video.x = x;
+            video.y = y;
+            render_dot(&mut video)
+        };
+        assert_eq!(rgb555_to_24(0x1234), colour_at(1, 1));
+        assert_eq!(rgb555_to_24(0), colour_at(0, 1));
+        assert_eq!(rgb555_to_24(0), colour_at(9, 1));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn affine_bg_works() {
+        let mut video = VideoController::new();
+        video.dispcnt.mode = 2;
+        video.dispcnt.display_bg[2] = true;
+        video.bgcnt[2].set_lo_bits(0b0000_0100); // Character base block 1
+        video.bgcnt[2].set_hi_bits(0b0000_0001); // 128x128, screen base block 1
+        video.palette_ram.as_mut().write_hword(2 * 0x42, 0x5555);
+
+        // Tile 3 at (1, 0) on the map
+        video.vram[0x801] = 3;
+        video.vram[0x4000 + 3 * 64] = 0x42;
+        video.vram[0x4000 + 3 * 64 + 8] = 0x42;
+
+        let write_params = |video: &mut VideoController, params: [u16; 4], x: u32, y: u32| {
+            for (i, param) in params.into_iter().enumerate() {
+                let [lo, hi] = param.to_le_bytes();
+                video.bgaffine[0].write_byte(2 * i as u32, lo);
+                video.bgaffine[0].write_byte(2 * i as u32 + 1, hi);
+            }
+            for (i, byte) in x.to_le_bytes().into_iter().enumerate() {
+                video.bgaffine[0].write_byte(8 + i as u32, byte);
+            }
+            for (i, byte) in y.to_le_bytes().into_iter().enumerate() {
+                video.bgaffine[0].write_byte(0xc + i as u32, byte);
+            }
+        };
+        let colour_at = |video: &mut VideoController, x| {
+            video.x = x;
+            video.bgaffine[0].latch_ref_point();
+            render_dot(video)
+        };
+
+        // Scaled up horizontally by 2
+        write_params(&mut video, [0x80, 0, 0, 0x100], 0, 0);
+        assert_eq!(rgb555_to_24(0x5555), colour_at(&mut video, 16));
+        assert_eq!(rgb555_to_24(0x5555), colour_at(&mut video, 17));
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, 18));
+
+        // Moved right by 120 dots, with the upper bits of the 28-bit reference point ignored
+        write_params(&mut video, [0x100, 0, 0, 0x100], 0x3fff_8800, 0);
+        assert_eq!(-120 * 0x100, video.bgaffine[0].ref_point[0]);
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, 0));
+        assert_eq!(rgb555_to_24(0x5555), colour_at(&mut video, 128));
+        video.bgcnt[2].wraparound = true;
+        assert_eq!(rgb555_to_24(0x5555), colour_at(&mut video, 0));
+
+        // The next scanline starts one dot down, then the reference point is reloaded
+        video.bgaffine[0].advance_ref_point();
+        assert_eq!(rgb555_to_24(0x5555), colour_at(&mut video, 0));
+        video.bgaffine[0].advance_ref_point();
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, 0));
+        video.bgaffine[0].reload_ref_point();
+        assert_eq!(rgb555_to_24(0x5555), colour_at(&mut video, 0));
+    }
+
+    #[test]
+    fn bitmap_bg_works() {
+        let mut video = VideoController::new();
+        video.dispcnt.display_bg[2] = true;
+        video.palette_ram.as_mut().write_hword(0, 0x1111);
+        video.palette_ram.as_mut().write_hword(2 * 0x80, 0x2222);
+
+        let colour_at = |video: &mut VideoController, x, y| {
+            video.x = x;
+            video.y = y;
+            for _ in 0..y {
+                video.bgaffine[0].advance_ref_point();
+            }
+            video.bgaffine[0].latch_ref_point();
+            let colour = render_dot(video);
+            video.bgaffine[0].reload_ref_point();
+
+            colour
+        };
+
+        video.dispcnt.mode = 3;
+        video.vram.as_mut().write_hword(2 * (240 + 2), 0x3333);
+        assert_eq!(rgb555_to_24(0x3333), colour_at(&mut video, 2, 1));
+
+        // Scaled down horizontally by 2
+        video.bgaffine[0].params[0] = 0x200;
+        assert_eq!(rgb555_to_24(0x3333), colour_at(&mut video, 1, 1));
+        video.bgaffine[0].params[0] = 0x100;
+
+        // Palette colour 0 is transparent
+        video.dispcnt.mode = 4;
+        video.dispcnt.frame_select = 1;
+        video.vram[0xa000 + FRAME_WIDTH + 2] = 0x80;
+        assert_eq!(rgb555_to_24(0x2222), colour_at(&mut video, 2, 1));
+        assert_eq!(rgb555_to_24(0x1111), colour_at(&mut video, 3, 1));
+
+        // Mode 5 is only 160x128, with the backdrop shown outside of it
+        video.dispcnt.mode = 5;
+        video
+            .vram
+            .as_mut()
+            .write_hword(0xa000 + 2 * (160 + 2), 0x4444);
+        assert_eq!(rgb555_to_24(0x4444), colour_at(&mut video, 2, 1));
+        video
+            .vram
+            .as_mut()
+            .write_hword(0xa000 + 2 * (160 * 127 + 159), 0x5555);
+        assert_eq!(rgb555_to_24(0x5555), colour_at(&mut video, 159, 127));
+        assert_eq!(rgb555_to_24(0x1111), colour_at(&mut video, 160, 127));
+        assert_eq!(rgb555_to_24(0x1111), colour_at(&mut video, 159, 128));
+    }
+
+    #[test]
+    fn obj_works() {
+        let mut video = VideoController::new();
+        video.dispcnt.display_obj = true;
+        video.dispcnt.obj_1d = true;
+        video
+            .palette_ram
+            .as_mut()
+            .write_hword(0x200 + 2 * 19, 0x7fff);
+        video
+            .palette_ram
+            .as_mut()
+            .write_hword(0x200 + 2 * 0x42, 0x1234);
+
+        // 16x8 4-bit depth OBJ at (-2, -6) with tile 4, palette 1 and priority 1, flipped
+        // horizontally
+        video.oam.as_mut().write_hword(0, 0x40fa);
+        video.oam.as_mut().write_hword(2, 0x11fe);
+        video.oam.as_mut().write_hword(4, 0x1404);
+        video.vram[0x1_0000 + 5 * 32 + 4 * 6 + 2] = 0x30;
+
+        // An OBJ with the same priority and palette 2 covering the same area, which is drawn below
+        // the first
+        video.oam.as_mut().write_hword(8, 0x40fa);
+        video.oam.as_mut().write_hword(10, 0x01fe);
+        video.oam.as_mut().write_hword(12, 0x2404);
+        video.vram[0x1_0000 + 4 * 32 + 4 * 6 + 1] = 0x50;
+        video
+            .palette_ram
+            .as_mut()
+            .write_hword(0x200 + 2 * 37, 0x0bad);
+
+        let colour_at = |video: &mut VideoController, x, y| {
+            video.x = x;
+            video.y = y;
+            render_dot(video)
+        };
+        assert_eq!(rgb555_to_24(0x7fff), colour_at(&mut video, 0, 0));
+        assert_eq!(rgb555_to_24(0x0bad), colour_at(&mut video, 1, 0));
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, 2, 0));
+
+        // Drawn above backgrounds with the same or lower priority only
+        video.palette_ram.as_mut().write_hword(2, 0x4321);
+        video.vram[0] = 1;
+        video.bgcnt[0].base_block = 1;
+        video.dispcnt.display_bg[0] = true;
+        assert_eq!(rgb555_to_24(0x4321), colour_at(&mut video, 0, 0));
+        video.bgcnt[0].priority = 1;
+        assert_eq!(rgb555_to_24(0x7fff), colour_at(&mut video, 0, 0));
+        video.dispcnt.display_bg[0] = false;
+
+        // 8x16 8-bit depth OBJ with tile 5 at (0, 0); in 2D mapping mode, the tile below is 32 tiles
+        // later, and bit 0 of the tile number is ignored
+        video.oam.as_mut().write_hword(0, 0xa000);
+        video.oam.as_mut().write_hword(2, 0x0000);
+        video.oam.as_mut().write_hword(4, 0x0005);
+        video.vram[0x1_0000 + 7 * 32] = 0x42;
+        video.vram[0x1_0000 + 36 * 32] = 0x42;
+        assert_eq!(rgb555_to_24(0x1234), colour_at(&mut video, 0, 8));
+        video.dispcnt.obj_1d = false;
+        video.vram[0x1_0000 + 7 * 32] = 0;
+        assert_eq!(rgb555_to_24(0x1234), colour_at(&mut video, 0, 8));
+
+        // Tiles below 512 can't be used in bitmap modes
+        video.dispcnt.mode = 3;
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, 0, 8));
+        video.oam.as_mut().write_hword(4, 0x0205);
+        video.vram[0x1_0000 + (512 + 36) * 32] = 0x42;
+        assert_eq!(rgb555_to_24(0x1234), colour_at(&mut video, 0, 8));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn affine_obj_works() {
+        let mut video = VideoController::new();
+        video.dispcnt.display_obj = true;
+        video.dispcnt.obj_1d = true;
+        video.palette_ram.as_mut().write_hword(0x200 + 2, 0x7fff);
+        disable_objs(&mut video);
+
+        // 8x8 affine OBJ at (0, 0) using parameter group 1, with only its top-right dot opaque
+        video.oam.as_mut().write_hword(0, 0x0100);
+        video.oam.as_mut().write_hword(2, 0x0200);
+        video.vram[0x1_0000 + 3] = 0x10;
+
+        let colour_at = |video: &mut VideoController, params: [u16; 4], x, y| {
+            for (i, param) in params.into_iter().enumerate() {
+                video.oam.as_mut().write_hword(32 + 6 + 8 * i as u32, param);
+            }
+            video.x = x;
+            video.y = y;
+            render_dot(video)
+        };
+        let identity = [0x100, 0, 0, 0x100];
+        assert_eq!(rgb555_to_24(0x7fff), colour_at(&mut video, identity, 7, 0));
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, identity, 0, 7));
+
+        // Transposed
+        let transpose = [0, 0x100, 0x100, 0];
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, transpose, 7, 0));
+        assert_eq!(rgb555_to_24(0x7fff), colour_at(&mut video, transpose, 0, 7));
+
+        // Scaled up by 2 and double size, so it covers a 16x16 area
+        let scale2 = [0x80, 0, 0, 0x80];
+        video.oam.as_mut().write_hword(0, 0x0300);
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, scale2, 13, 0));
+        assert_eq!(rgb555_to_24(0x7fff), colour_at(&mut video, scale2, 14, 0));
+        assert_eq!(rgb555_to_24(0x7fff), colour_at(&mut video, scale2, 15, 1));
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, scale2, 15, 2));
+
+        // Moved to (-1, 0), so it's clipped by the left edge of the screen
+        video.oam.as_mut().write_hword(2, 0x03ff);
+        assert_eq!(rgb555_to_24(0x7fff), colour_at(&mut video, scale2, 13, 0));
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, scale2, 15, 0));
+
+        // Regular OBJs with the double size flag set are disabled
+        video.oam.as_mut().write_hword(2, 0x0000);
+        video.oam.as_mut().write_hword(0, 0x0200);
+        assert_eq!(rgb555_to_24(0), colour_at(&mut video, identity, 7, 0));
+        video.oam.as_mut().write_hword(0, 0x0000);
+        assert_eq!(rgb555_to_24(0x7fff), colour_at(&mut video, identity, 7, 0));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn top_layers_works() {
+        let mut video = VideoController::new();
+        video.dispcnt.display_obj = true;
+        video.dispcnt.display_bg = [true; 4];
+        video.palette_ram.as_mut().write_hword(0, 0x1111);
+        video.palette_ram.as_mut().write_hword(2, 0x2222);
+        video.palette_ram.as_mut().write_hword(0x200 + 2, 0x3333);
+
+        // Opaque backgrounds using tile 0 with screen base block 1, and an opaque OBJ
+        video.vram[0] = 1;
+        for bgcnt in &mut video.bgcnt {
+            bgcnt.base_block = 1;
+        }
+        video.vram[0x1_0000] = 1;
+        disable_objs(&mut video);
+
+        let layers_at = |video: &mut VideoController| {
+            render_dot(video);
+            video
+                .top_layers(0, WindowControl::all())
+                .map(|dot| dot.layer)
+        };
+
+        video.bgcnt[0].priority = 3;
+        video.bgcnt[1].priority = 2;
+        video.bgcnt[2].priority = 2;
+        video.bgcnt[3].priority = 1;
+        assert_eq!([Layer::Obj, Layer::Bg(3)], layers_at(&mut video));
+
+        video.dispcnt.display_obj = false;
+        video.dispcnt.display_bg[3] = false;
+        assert_eq!([Layer::Bg(1), Layer::Bg(2)], layers_at(&mut video));
+
+        video.dispcnt.display_obj = true;
+        video.oam.as_mut().write_hword(4, 3 << 10); // Priority 3
+        assert_eq!([Layer::Bg(1), Layer::Bg(2)], layers_at(&mut video));
+        video.dispcnt.display_bg = [true, false, false, false];
+        assert_eq!([Layer::Obj, Layer::Bg(0)], layers_at(&mut video));
+        assert_eq!(0x3333, video.top_layers(0, WindowControl::all())[0].colour);
+
+        video.dispcnt.display_obj = false;
+        assert_eq!([Layer::Bg(0), Layer::Backdrop], layers_at(&mut video));
+        assert_eq!(0x1111, video.top_layers(0, WindowControl::all())[1].colour);
+        video.dispcnt.display_bg[0] = false;
+        assert_eq!([Layer::Backdrop, Layer::Backdrop], layers_at(&mut video));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn window_works() {
+        let mut video = VideoController::new();
+        video.winin[0].set_bits(0b00_0001);
+        video.winin[1].set_bits(0b00_0010);
+        video.objwin.set_bits(0b00_0100);
+        video.winout.set_bits(0b10_1000);
+        let window_at = |video: &VideoController, x, y| video.window_control(x, y).bits();
+
+        // No windows displayed
+        assert_eq!(0b11_1111, window_at(&video, 0, 0));
+
+        // WIN0 wraps around horizontally, and its bottom is clamped to the screen
+        video.dispcnt.display_window[0] = true;
+        video.win_bounds[0] = WindowBounds {
+            left: 200,
+            right: 10,
+            top: 150,
+            bottom: 255,
+        };
+        assert_eq!(0b00_0001, window_at(&video, 0, 150));
+        assert_eq!(0b00_0001, window_at(&video, 239, 159));
+        assert_eq!(0b00_0001, window_at(&video, 200, 159));
+        assert_eq!(0b10_1000, window_at(&video, 10, 159));
+        assert_eq!(0b10_1000, window_at(&video, 199, 159));
+        assert_eq!(0b10_1000, window_at(&video, 0, 149));
+
+        // WIN1 is below WIN0
+        video.dispcnt.display_window[1] = true;
+        video.win_bounds[1] = WindowBounds {
+            left: 0,
+            right: 240,
+            top: 100,
+            bottom: 155,
+        };
+        assert_eq!(0b00_0001, window_at(&video, 0, 150));
+        assert_eq!(0b00_0010, window_at(&video, 100, 150));
+        assert_eq!(0b10_1000, window_at(&video, 100, 155));
+
+        // The OBJ window is below WIN1, and is made up of the opaque dots of OBJ window OBJs
+        disable_objs(&mut video);
+        video.oam.as_mut().write_hword(0, 0x0863); // OBJ window mode at (0, 99)
+        video.vram[0x1_0000] = 0x01;
+        video.palette_ram.as_mut().write_hword(0x200 + 2, 0x7fff);
+        video.dispcnt.display_obj = true;
+        video.dispcnt.display_obj_window = true;
+        video.y = 99;
+        video.render_dots(0..FRAME_WIDTH);
+        assert_eq!(0b00_0100, window_at(&video, 0, 99));
+        assert_eq!(0b10_1000, window_at(&video, 1, 99));
+        assert_eq!(0b00_0010, window_at(&video, 0, 100));
+
+        // OBJ window OBJs aren't displayed themselves
+        video.x = 0;
+        video.y = 99;
+        video.winout.display_obj = true;
+        video.objwin.display_obj = true;
+        assert_eq!(rgb555_to_24(0), render_dot(&mut video));
+
+        // Layers are hidden outside of the windows that enable them
+        video.dispcnt.display_bg[0] = true;
+        video.vram[0] = 1;
+        video.bgcnt[0].base_block = 1;
+        video.palette_ram.as_mut().write_hword(2, 0x1234);
+        assert_eq!(rgb555_to_24(0), render_dot(&mut video));
+        video.y = 152;
+        assert_eq!(rgb555_to_24(0x1234), render_dot(&mut video));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn colour_effects_work() {
+        let mut video = VideoController::new();
+        disable_objs(&mut video);
+
+        // Opaque BG0 over a backdrop
+        video.dispcnt.display_bg[0] = true;
+        video.bgcnt[0].base_block = 1;
+        video.vram[0] = 1;
+        video
+            .palette_ram
+            .as_mut()
+            .write_hword(0, rgb555_from_channels([4, 8, 31]));
+        video
+            .palette_ram
+            .as_mut()
+            .write_hword(2, rgb555_from_channels([16, 8, 20]));
+
+        // BG0 blended onto the backdrop, saturating the blue channel
+        video.bldcnt.set_lo_bits(0b0100_0001);
+        video.bldcnt.set_hi_bits(0b0010_0000);
+        video.bldalpha = [8, 12];
+        let expected = rgb555_from_channels([8 + 3, 4 + 6, 31]);
+        assert_eq!(rgb555_to_24(expected), render_dot(&mut video));
+
+        // Coefficients above 16 are treated as 16
+        video.bldalpha = [31, 0];
+        let bg0 = rgb555_from_channels([16, 8, 20]);
+        assert_eq!(rgb555_to_24(bg0), render_dot(&mut video));
+
+        // Not blended if the layer below isn't a second target
+        video.bldalpha = [8, 12];
+        video.bldcnt.set_hi_bits(0);
+        assert_eq!(rgb555_to_24(bg0), render_dot(&mut video));
+
+        video.bldcnt.set_lo_bits(0b1000_0001);
+        video.bldy = 8;
+        let expected = rgb555_from_channels([23, 19, 25]);
+        assert_eq!(rgb555_to_24(expected), render_dot(&mut video));
+        video.bldcnt.set_lo_bits(0b1100_0001);
+        let expected = rgb555_from_channels([8, 4, 10]);
+        assert_eq!(rgb555_to_24(expected), render_dot(&mut video));
+
+        // Semi-transparent OBJs are alpha blended if the layer below is a second target, even if
+        // they aren't a first target
+        video.dispcnt.display_obj = true;
+        video.oam.as_mut().write_hword(0, 0x0400);
+        video.vram[0x1_0000] = 1;
+        video
+            .palette_ram
+            .as_mut()
+            .write_hword(0x202, rgb555_from_channels([0, 16, 0]));
+        video.bldcnt.set_hi_bits(0b0000_0001);
+        let expected = rgb555_from_channels([12, 8 + 6, 15]);
+        assert_eq!(rgb555_to_24(expected), render_dot(&mut video));
+        video.bldcnt.set_hi_bits(0);
+        let obj = rgb555_from_channels([0, 16, 0]);
+        assert_eq!(rgb555_to_24(obj), render_dot(&mut video));
+
+        // Effects disabled by the window
+        video.bldcnt.set_hi_bits(0b0000_0001);
+        video.dispcnt.display_window[0] = true;
+        video.winout.set_bits(0b01_0001);
+        video.winout.display_obj = true;
+        assert_eq!(rgb555_to_24(obj), render_dot(&mut video));
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn mosaic_works() {
+        let mut video = VideoController::new();
+        disable_objs(&mut video);
+        video.palette_ram.as_mut().write_hword(2, 0x1111);
+        video.palette_ram.as_mut().write_hword(0x202, 0x2222);
+
+        // 3x2 BG mosaic and 2x3 OBJ mosaic
+        video.mosaic.set_lo_bits(0x12);
+        video.mosaic.set_hi_bits(0x21);
+
+        // BG0 with only the dots at (3, 0) and (3, 2) of tile 0 opaque
+        video.dispcnt.display_bg[0] = true;
+        video.bgcnt[0].base_block = 1;
+        video.bgcnt[0].mosaic = true;
+        video.vram[1] = 0x10;
+        video.vram[4 * 2 + 1] = 0x10;
+
+        let colour_at = |video: &mut VideoController, x, y| {
+            video.x = x;
+            if y < video.y || y == 0 {
+                video.y = 0;
+                video.update_mosaic_y();
+            }
+            while video.y < y {
+                video.y += 1;
+                video.update_mosaic_y();
+            }
+            render_dot(video)
+        };
+        for (y, expected) in [(0, 0x1111), (1, 0x1111), (2, 0x1111), (3, 0x1111), (4, 0)] {
+            assert_eq!(rgb555_to_24(0), colour_at(&mut video, 2, y));
+            assert_eq!(rgb555_to_24(expected), colour_at(&mut video, 3, y));
+            assert_eq!(rgb555_to_24(expected), colour_at(&mut video, 5, y));
+            assert_eq!(rgb555_to_24(0), colour_at(&mut video, 6, y));
+        }
+
+        // OBJ at (1, 3) with only its top-left dot opaque; mosaic blocks start from its left, but
+        // vertically are counted from the top of the screen
+        video.dispcnt.display_bg[0] = false;
+        video.dispcnt.display_obj = true;
+        video.oam.as_mut().write_hword(0, 0x1003);
+        video.oam.as_mut().write_hword(2, 0x0001);
+        video.vram[0x1_0000] = 0x01;
+        for (y, expected) in [(2, 0), (3, 0x2222), (4, 0x2222), (5, 0x2222), (6, 0)] {
+            assert_eq!(rgb555_to_24(0), colour_at(&mut video, 0, y));
+            assert_eq!(rgb555_to_24(expected), colour_at(&mut video, 1, y));
+            assert_eq!(rgb555_to_24(expected), colour_at(&mut video, 2, y));
+            assert_eq!(rgb555_to_24(0), colour_at(&mut video, 3, y));
+        }
+    }
+}
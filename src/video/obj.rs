@@ -0,0 +1,114 @@
+use intbits::Bits;
+
+use crate::bus::Bus;
+
+pub const OBJ_COUNT: usize = 128;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ObjMode {
+    Normal,
+    SemiTransparent,
+    Window,
+    Prohibited,
+}
+
+/// Returns the PA, PB, PC and PD rotation and scaling parameters of group `idx`, which are stored in
+/// the unused fourth halfwords of each object's attributes.
+pub fn affine_params(oam: &[u8], idx: u8) -> [i16; 4] {
+    let base_offset = 32 * u32::from(idx) + 6;
+    #[allow(clippy::cast_possible_wrap)]
+    [0, 1, 2, 3].map(|i| oam.read_hword(base_offset + 8 * i) as i16)
+}
+
+/// Width and height of objects in dots, indexed by shape and size.
+const OBJ_SIZES: [[(usize, usize); 4]; 3] = [
+    [(8, 8), (16, 16), (32, 32), (64, 64)],
+    [(16, 8), (32, 8), (32, 16), (64, 32)],
+    [(8, 16), (8, 32), (16, 32), (32, 64)],
+];
+
+/// The attributes of an object (sprite) in OAM.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Copy, Clone, Debug)]
+pub struct Object {
+    pub x: u16,
+    pub y: u8,
+    pub affine: bool,
+    /// Disables the object if it isn't affine.
+    pub double_size: bool,
+    pub mode: ObjMode,
+    pub mosaic: bool,
+    pub color256: bool,
+    pub shape: u8,
+    pub size: u8,
+    pub flip_horiz: bool,
+    pub flip_vert: bool,
+    pub affine_params_idx: u8,
+    pub tile_idx: u16,
+    pub priority: u8,
+    pub palette_idx: u8,
+}
+
+impl Object {
+    pub fn from_oam(oam: &[u8], idx: usize) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        let offset = 8 * idx as u32;
+        let attr0 = oam.read_hword(offset);
+        let attr1 = oam.read_hword(offset + 2);
+        let attr2 = oam.read_hword(offset + 4);
+
+        #[allow(clippy::cast_possible_truncation)]
+        Self {
+            y: attr0.bits(..8) as u8,
+            affine: attr0.bit(8),
+            double_size: attr0.bit(9),
+            mode: match attr0.bits(10..12) {
+                0 => ObjMode::Normal,
+                1 => ObjMode::SemiTransparent,
+                2 => ObjMode::Window,
+                _ => ObjMode::Prohibited,
+            },
+            mosaic: attr0.bit(12),
+            color256: attr0.bit(13),
+            shape: attr0.bits(14..) as u8,
+            x: attr1.bits(..9),
+            flip_horiz: attr1.bit(12),
+            flip_vert: attr1.bit(13),
+            affine_params_idx: attr1.bits(9..14) as u8,
+            size: attr1.bits(14..) as u8,
+            tile_idx: attr2.bits(..10),
+            priority: attr2.bits(10..12) as u8,
+            palette_idx: attr2.bits(12..) as u8,
+        }
+    }
+
+    pub fn is_displayed(&self) -> bool {
+        (self.affine || !self.double_size) && self.shape != 3 && self.mode != ObjMode::Prohibited
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        OBJ_SIZES[usize::from(self.shape)][usize::from(self.size)]
+    }
+
+    /// Width and height of the area of the screen that the object covers, which is twice its
+    /// dimensions for affine objects using the double-size flag.
+    pub fn bounds(&self) -> (usize, usize) {
+        let (width, height) = self.dimensions();
+        if self.affine && self.double_size {
+            (2 * width, 2 * height)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Returns the position of the dot at (`x`, `y`) on the screen relative to the top-left of the
+    /// object's bounds, or `None` if the object doesn't cover it. Objects wrap around the edges of
+    /// the 512x256 coordinate space.
+    pub fn dot_pos(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let (width, height) = self.bounds();
+        let dot_x = (x + 512 - usize::from(self.x)) % 512;
+        let dot_y = (y + 256 - usize::from(self.y)) % 256;
+
+        (dot_x < width && dot_y < height).then_some((dot_x, dot_y))
+    }
+}
@@ -1,7 +1,10 @@
 use intbits::Bits;
+use strum_macros::FromRepr;
+
+use super::{FRAME_HEIGHT, FRAME_WIDTH};
 
 #[allow(clippy::struct_excessive_bools)]
-#[derive(Default, Debug)]
+#[derive(Copy, Clone, Default, Debug)]
 pub struct DisplayControl {
     pub mode: u8,
     pub frame_select: usize,
@@ -176,7 +179,249 @@ impl BackgroundControl {
         0x800 * usize::from(self.base_block)
     }
 
+    /// Width and height of the map in dots when used as a text background.
+    pub fn text_screen_size(self) -> (usize, usize) {
+        match self.screen_size {
+            0 => (256, 256),
+            1 => (512, 256),
+            2 => (256, 512),
+            _ => (512, 512),
+        }
+    }
+
+    /// Width and height of the map in dots when used as an affine background.
+    pub fn affine_screen_size(self) -> usize {
+        128 << self.screen_size
+    }
+
     pub fn dots_vram_offset(self) -> usize {
         0x4000 * usize::from(self.dots_base_block)
     }
 }
+
+/// Rotation and scaling parameters of BG2 or BG3.
+#[derive(Copy, Clone, Debug)]
+pub struct AffineBackground {
+    /// PA, PB, PC and PD, as signed 8.8 fixed-point numbers.
+    pub params: [i16; 4],
+    /// X and Y of the reference point, as signed 20.8 fixed-point numbers.
+    pub ref_point: [i32; 2],
+    /// Reloaded from `ref_point` when it is written and at the start of each frame, then advanced
+    /// by PB and PD after each scanline is drawn.
+    pub internal_ref_point: [i32; 2],
+    /// The internal reference point latched at the start of the current scanline, which is used to
+    /// draw it, so writes made mid-scanline only take effect from the next one.
+    pub line_ref_point: [i32; 2],
+}
+
+impl Default for AffineBackground {
+    fn default() -> Self {
+        // Not scaled or rotated.
+        Self {
+            params: [0x100, 0, 0, 0x100],
+            ref_point: [0; 2],
+            internal_ref_point: [0; 2],
+            line_ref_point: [0; 2],
+        }
+    }
+}
+
+impl AffineBackground {
+    /// Writes to the register at `offset` from `BGxPA`.
+    pub fn write_byte(&mut self, offset: u32, value: u8) {
+        let byte_idx = offset as usize % 4;
+        match offset {
+            0x0..=0x7 => {
+                let param = &mut self.params[offset as usize / 2];
+                let mut bytes = param.to_le_bytes();
+                bytes[byte_idx % 2] = value;
+                *param = i16::from_le_bytes(bytes);
+            }
+            0x8..=0xf => {
+                let ref_idx = (offset as usize - 0x8) / 4;
+                let mut bytes = self.ref_point[ref_idx].to_le_bytes();
+                bytes[byte_idx] = value;
+
+                // Sign-extend from 28 bits.
+                self.ref_point[ref_idx] = i32::from_le_bytes(bytes) << 4 >> 4;
+                self.internal_ref_point[ref_idx] = self.ref_point[ref_idx];
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn reload_ref_point(&mut self) {
+        self.internal_ref_point = self.ref_point;
+    }
+
+    pub fn advance_ref_point(&mut self) {
+        let [x, y] = &mut self.internal_ref_point;
+        *x = x.wrapping_add(self.params[1].into());
+        *y = y.wrapping_add(self.params[3].into());
+    }
+
+    pub fn latch_ref_point(&mut self) {
+        self.line_ref_point = self.internal_ref_point;
+    }
+
+    /// Returns the position of a dot on the screen in the background, as 20.8 fixed-point numbers.
+    /// `lines_back` is the number of scanlines above the current one that the dot is on, which is
+    /// non-zero when using vertical mosaic.
+    pub fn transform(&self, dot_x: usize, lines_back: usize) -> (i32, i32) {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let (dot_x, lines_back) = (dot_x as i32, lines_back as i32);
+        let [ref_x, ref_y] = self.line_ref_point;
+        let [pa, pb, pc, pd] = self.params.map(i32::from);
+
+        (
+            ref_x.wrapping_add(pa * dot_x).wrapping_sub(pb * lines_back),
+            ref_y.wrapping_add(pc * dot_x).wrapping_sub(pd * lines_back),
+        )
+    }
+}
+
+/// Bounds of WIN0 or WIN1, set by `WINxH` and `WINxV`.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct WindowBounds {
+    pub left: u8,
+    pub right: u8,
+    pub top: u8,
+    pub bottom: u8,
+}
+
+impl WindowBounds {
+    pub fn contains(self, x: usize, y: usize) -> bool {
+        // The end is exclusive, and is clamped to the edge of the screen. If the start is after the
+        // end, the window wraps around the edges of the screen.
+        let in_range = |pos: usize, start: u8, end: u8, len: usize| {
+            let (start, end) = (usize::from(start), usize::from(end).min(len));
+            if start <= end {
+                (start..end).contains(&pos)
+            } else {
+                pos >= start || pos < end
+            }
+        };
+
+        in_range(x, self.left, self.right, FRAME_WIDTH)
+            && in_range(y, self.top, self.bottom, FRAME_HEIGHT)
+    }
+}
+
+/// The layers and effects enabled inside a window, set by `WININ` or `WINOUT`.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct WindowControl {
+    pub display_bg: [bool; 4],
+    pub display_obj: bool,
+    pub effects: bool,
+}
+
+impl WindowControl {
+    /// Enables all layers and effects, which is used when no windows are displayed.
+    pub fn all() -> Self {
+        Self {
+            display_bg: [true; 4],
+            display_obj: true,
+            effects: true,
+        }
+    }
+
+    pub fn bits(self) -> u8 {
+        let mut bits = 0;
+        bits.set_bit(0, self.display_bg[0]);
+        bits.set_bit(1, self.display_bg[1]);
+        bits.set_bit(2, self.display_bg[2]);
+        bits.set_bit(3, self.display_bg[3]);
+        bits.set_bit(4, self.display_obj);
+        bits.set_bit(5, self.effects);
+
+        bits
+    }
+
+    pub fn set_bits(&mut self, bits: u8) {
+        self.display_bg[0] = bits.bit(0);
+        self.display_bg[1] = bits.bit(1);
+        self.display_bg[2] = bits.bit(2);
+        self.display_bg[3] = bits.bit(3);
+        self.display_obj = bits.bit(4);
+        self.effects = bits.bit(5);
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, FromRepr)]
+#[repr(u8)]
+pub enum ColourEffect {
+    None,
+    AlphaBlend,
+    Brighten,
+    Darken,
+}
+
+/// Colour special effect selection, set by `BLDCNT`.
+#[derive(Copy, Clone, Debug)]
+pub struct BlendControl {
+    /// First and second targets, each indexed by BG0-BG3, OBJ, then the backdrop.
+    pub targets: [[bool; 6]; 2],
+    pub effect: ColourEffect,
+}
+
+impl Default for BlendControl {
+    fn default() -> Self {
+        Self {
+            targets: [[false; 6]; 2],
+            effect: ColourEffect::None,
+        }
+    }
+}
+
+impl BlendControl {
+    pub fn lo_bits(self) -> u8 {
+        let mut bits = 0;
+        for (i, &target) in self.targets[0].iter().enumerate() {
+            bits.set_bit(i, target);
+        }
+        bits.set_bits(6.., self.effect as u8);
+
+        bits
+    }
+
+    pub fn hi_bits(self) -> u8 {
+        let mut bits = 0;
+        for (i, &target) in self.targets[1].iter().enumerate() {
+            bits.set_bit(i, target);
+        }
+
+        bits
+    }
+
+    pub fn set_lo_bits(&mut self, bits: u8) {
+        for (i, target) in self.targets[0].iter_mut().enumerate() {
+            *target = bits.bit(i);
+        }
+        self.effect = ColourEffect::from_repr(bits.bits(6..)).unwrap();
+    }
+
+    pub fn set_hi_bits(&mut self, bits: u8) {
+        for (i, target) in self.targets[1].iter_mut().enumerate() {
+            *target = bits.bit(i);
+        }
+    }
+}
+
+/// Mosaic sizes, set by `MOSAIC`.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Mosaic {
+    /// Width and height of the BG mosaic blocks, minus 1.
+    pub bg: [u8; 2],
+    /// Width and height of the OBJ mosaic blocks, minus 1.
+    pub obj: [u8; 2],
+}
+
+impl Mosaic {
+    pub fn set_lo_bits(&mut self, bits: u8) {
+        self.bg = [bits.bits(..4), bits.bits(4..)];
+    }
+
+    pub fn set_hi_bits(&mut self, bits: u8) {
+        self.obj = [bits.bits(..4), bits.bits(4..)];
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    mem,
+    ops::Range,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use super::{
+    reg::{
+        AffineBackground, BackgroundControl, BlendControl, DisplayControl, Mosaic, WindowBounds,
+        WindowControl,
+    },
+    FrameBuffer, VideoController, VideoMemory,
+};
+
+/// A snapshot of the registers used to draw a range of dots of a scanline.
+#[derive(Copy, Clone, Debug)]
+struct RenderState {
+    y: u8,
+    dispcnt: DisplayControl,
+    green_swap: u16,
+    bgcnt: [BackgroundControl; 4],
+    bghofs: [u16; 4],
+    bgvofs: [u16; 4],
+    bgaffine: [AffineBackground; 2],
+    win_bounds: [WindowBounds; 2],
+    winin: [WindowControl; 2],
+    winout: WindowControl,
+    objwin: WindowControl,
+    bldcnt: BlendControl,
+    bldalpha: [u8; 2],
+    bldy: u8,
+    mosaic: Mosaic,
+    bg_mosaic_y: u8,
+    obj_mosaic_y: u8,
+}
+
+impl RenderState {
+    fn new(video: &VideoController) -> Self {
+        Self {
+            y: video.y,
+            dispcnt: video.dispcnt,
+            green_swap: video.green_swap,
+            bgcnt: video.bgcnt,
+            bghofs: video.bghofs,
+            bgvofs: video.bgvofs,
+            bgaffine: video.bgaffine,
+            win_bounds: video.win_bounds,
+            winin: video.winin,
+            winout: video.winout,
+            objwin: video.objwin,
+            bldcnt: video.bldcnt,
+            bldalpha: video.bldalpha,
+            bldy: video.bldy,
+            mosaic: video.mosaic,
+            bg_mosaic_y: video.bg_mosaic_y,
+            obj_mosaic_y: video.obj_mosaic_y,
+        }
+    }
+
+    fn restore(self, video: &mut VideoController) {
+        video.y = self.y;
+        video.dispcnt = self.dispcnt;
+        video.green_swap = self.green_swap;
+        video.bgcnt = self.bgcnt;
+        video.bghofs = self.bghofs;
+        video.bgvofs = self.bgvofs;
+        video.bgaffine = self.bgaffine;
+        video.win_bounds = self.win_bounds;
+        video.winin = self.winin;
+        video.winout = self.winout;
+        video.objwin = self.objwin;
+        video.bldcnt = self.bldcnt;
+        video.bldalpha = self.bldalpha;
+        video.bldy = self.bldy;
+        video.mosaic = self.mosaic;
+        video.bg_mosaic_y = self.bg_mosaic_y;
+        video.obj_mosaic_y = self.obj_mosaic_y;
+    }
+}
+
+/// A hword written to video memory.
+type MemoryWrite = (VideoMemory, u32, u16);
+
+enum Job {
+    /// Replays the writes made to video memory since the last job, then draws a range of dots.
+    Render {
+        writes: Vec<MemoryWrite>,
+        state: Box<RenderState>,
+        dots: Range<usize>,
+    },
+    /// Sends back the drawn frame.
+    Present,
+    SetColourTable(Arc<[u32]>),
+}
+
+/// Draws scanlines on a background thread. The thread has its own copy of video memory, which is
+/// kept in sync by replaying the writes made to it in order with the dots drawn, so the frames it
+/// draws are the same as when drawing on the emulation thread.
+#[derive(Debug)]
+pub(super) struct RenderThread {
+    jobs: Sender<Job>,
+    frames: Receiver<FrameBuffer>,
+    writes: Vec<MemoryWrite>,
+}
+
+impl RenderThread {
+    /// Spawns a render thread, starting with a copy of the video memory of `video`.
+    pub fn spawn(video: &VideoController) -> Self {
+        let (jobs, job_receiver) = mpsc::channel();
+        let (frame_sender, frames) = mpsc::channel();
+        let mut renderer = VideoController::new();
+        renderer.palette_ram.clone_from(&video.palette_ram);
+        renderer.vram.clone_from(&video.vram);
+        renderer.oam.clone_from(&video.oam);
+        renderer.colour_table = Arc::clone(&video.colour_table);
+
+        thread::Builder::new()
+            .name("video".into())
+            .spawn(move || {
+                for job in job_receiver {
+                    match job {
+                        Job::Render {
+                            writes,
+                            state,
+                            dots,
+                        } => {
+                            for (mem, offset, value) in writes {
+                                renderer.write_hword(mem, offset, value);
+                            }
+                            state.restore(&mut renderer);
+                            renderer.draw_dots(dots);
+                        }
+                        Job::SetColourTable(table) => renderer.colour_table = table,
+                        Job::Present => {
+                            if frame_sender
+                                .send(mem::take(&mut renderer.frame_buf))
+                                .is_err()
+                            {
+                                break;
+                            }
+                        }
+                    }
+                }
+            })
+            .expect("failed to spawn render thread");
+
+        Self {
+            jobs,
+            frames,
+            writes: Vec::new(),
+        }
+    }
+
+    pub fn write_hword(&mut self, mem: VideoMemory, offset: u32, value: u16) {
+        self.writes.push((mem, offset, value));
+    }
+
+    /// Queues a range of dots of the current scanline of `video` to be drawn using the current
+    /// state of its registers.
+    pub fn render(&mut self, video: &VideoController, dots: Range<usize>) {
+        let job = Job::Render {
+            writes: mem::take(&mut self.writes),
+            state: Box::new(RenderState::new(video)),
+            dots,
+        };
+        self.send(job);
+    }
+
+    pub fn set_colour_table(&mut self, table: Arc<[u32]>) {
+        self.send(Job::SetColourTable(table));
+    }
+
+    /// Waits for all of the queued dots to be drawn, then returns the frame.
+    pub fn finish_frame(&mut self) -> FrameBuffer {
+        self.send(Job::Present);
+        self.frames.recv().expect("render thread stopped")
+    }
+
+    fn send(&self, job: Job) {
+        self.jobs.send(job).expect("render thread stopped");
+    }
+}